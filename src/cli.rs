@@ -1,15 +1,52 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
 use log::{debug, error, LevelFilter, Metadata, Record};
-use std::io::{self, Read};
-use yaml_rust::{Yaml, YamlLoader};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
 
+use crate::parallel::{for_each_ordered, load_docs_ordered};
 use crate::{
-    convert_length, convert_single_node, debug_print_doc_structure, parse_path, traverse,
-    VisitedNode,
+    convert_json, convert_length, convert_single_node, debug_print_doc_structure, delete_with,
+    expand_inputs, load_json, parse_path, parse_value, render_lossless, stream_count,
+    stream_search, traverse_with, write_with, Error, Marker, PathSegment, SourceMap, StreamEvent,
+    TraverseOptions, VisitedNode,
 };
 
 static LOGGER: SimpleLogger = SimpleLogger;
 
+const YAML_FILE_ARG: &str = "yaml_file";
+const PATH_EXPRESSION_ARG: &str = "path_expression";
+const EXPRESSION_ARG: &str = "expression";
+const INPUTS_ARG: &str = "inputs";
+const DEFAULT_VALUE_ARG: &str = "default_value";
+const LENGTH_ARG: &str = "length";
+const PRINT_MODE_ARG: &str = "print_mode";
+const OUTPUT_ARG: &str = "output";
+const INPUT_ARG: &str = "input";
+const COLLECT_ARG: &str = "collect";
+const DOC_IDX_ARG: &str = "doc_idx";
+const DEBUG_ARG: &str = "debug";
+const STREAM_ARG: &str = "stream";
+const STRICT_ARG: &str = "strict";
+const JOBS_ARG: &str = "jobs";
+const QUIET_ARG: &str = "quiet";
+const FIRST_ARG: &str = "first";
+const LIMIT_ARG: &str = "limit";
+const COUNT_ARG: &str = "count";
+const VALUE_ARG: &str = "value";
+const IN_PLACE_ARG: &str = "in_place";
+
+/// Exit status of a search that matched nothing, like grep.
+const NO_MATCH_EXIT_CODE: i32 = 1;
+/// Exit status on any error, like grep.
+const ERROR_EXIT_CODE: i32 = 2;
+
+const WRITE_SUBCOMMAND: &str = "write";
+const DELETE_SUBCOMMAND: &str = "delete";
+
 struct SimpleLogger;
 
 impl log::Log for SimpleLogger {
@@ -26,11 +63,12 @@ impl log::Log for SimpleLogger {
     fn flush(&self) {}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum PrintMode {
     Value,
     Path,
     ValueAndPath,
+    Location,
 }
 
 fn parse_print_mode(mode: &str) -> PrintMode {
@@ -39,246 +77,1103 @@ fn parse_print_mode(mode: &str) -> PrintMode {
         "p" => PrintMode::Path,
         "pv" => PrintMode::ValueAndPath,
         "vp" => PrintMode::ValueAndPath,
+        "l" => PrintMode::Location,
         _ => PrintMode::Value,
     }
 }
 
-pub fn run_cli() {
-    let yaml_file_arg = "yaml_file";
-    let path_expression_arg = "path_expression";
-    let default_value_arg = "default_value";
-    let length_arg = "length";
-    let print_mode_arg = "print_mode";
-    let collect_arg = "collect";
-    let doc_idx_arg = "doc_idx";
-    let debug_arg = "debug";
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CountMode {
+    Total,
+    PerDoc,
+}
+
+/// Returns how matches are counted with `--count`, or `None` to print them.
+fn parse_count_mode(matches: &ArgMatches) -> Option<CountMode> {
+    if !matches.is_present(COUNT_ARG) {
+        return None;
+    }
+    match matches.value_of(COUNT_ARG) {
+        Some("per-doc") => Some(CountMode::PerDoc),
+        _ => Some(CountMode::Total),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Yaml,
+    Json,
+    JsonCompact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InputFormat {
+    Yaml,
+    Json,
+}
+
+/// Uses the format given with `--input`, or json for files with a `.json` extension.
+fn input_format(matches: &ArgMatches, file_name: &str) -> InputFormat {
+    match matches.value_of(INPUT_ARG) {
+        Some("json") => InputFormat::Json,
+        Some(_) => InputFormat::Yaml,
+        None if file_name.to_lowercase().ends_with(".json") => InputFormat::Json,
+        None => InputFormat::Yaml,
+    }
+}
+
+fn parse_output_format(format: &str) -> OutputFormat {
+    match format {
+        "json" => OutputFormat::Json,
+        "json-compact" => OutputFormat::JsonCompact,
+        _ => OutputFormat::Yaml,
+    }
+}
+
+/// Logs the message of a failed result and exits.
+fn exit_on_err<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|msg| {
+        error!("{}", msg);
+        std::process::exit(ERROR_EXIT_CODE);
+    })
+}
+
+fn unwrap_or_exit<T>(result: Result<T, Error>, file_name: &str) -> T {
+    exit_on_err(result.map_err(|err| file_error(file_name, err)))
+}
 
+fn file_error(file_name: &str, err: Error) -> String {
+    format!("failed to process file `{}`: {}", file_name, err)
+}
+
+pub fn run_cli() {
     let matches = App::new("ry")
         .version("0.0")
         .author("Will Deuschle")
         .about("structured search in yaml files")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .usage(
+            "ry [FLAGS] [OPTIONS] <path_expression> <inputs>...\n    \
+             ry [FLAGS] [OPTIONS] -e <expression>... <inputs>...\n    \
+             ry [FLAGS] [OPTIONS] <yaml_file> <path_expression>",
+        )
+        .after_help(
+            "The path comes before the files, directories and glob patterns to search, like it does \
+             for grep, and with -e every positional argument is an input. The older \
+             `ry <yaml_file> <path_expression>` order is still accepted: when the first argument \
+             is an existing file or `-` and the second is not an existing file or directory, the \
+             first is the file and the second the path.",
+        )
         .arg(
-            Arg::with_name(yaml_file_arg)
-                .help("sets the input yaml file to use")
+            Arg::with_name(PATH_EXPRESSION_ARG)
+                .help("path to search against, or the first input with -e")
                 .required(true)
                 .index(1),
         )
         .arg(
-            Arg::with_name(path_expression_arg)
-                .help("path to search against")
-                .required(true)
+            Arg::with_name(INPUTS_ARG)
+                .help("files, directories or glob patterns to search, `-` for stdin")
+                .required_unless(EXPRESSION_ARG)
+                .multiple(true)
                 .index(2),
         )
         .arg(
-            Arg::with_name(default_value_arg)
+            Arg::with_name(EXPRESSION_ARG)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("another path to search against, results are grouped by path per document")
+                .long("expression")
+                .short("e"),
+        )
+        .arg(
+            Arg::with_name(DEFAULT_VALUE_ARG)
                 .takes_value(true)
                 .help("default value to print if there are no matching nodes")
                 .long("defaultValue"),
         )
         .arg(
-            Arg::with_name(length_arg)
+            Arg::with_name(LENGTH_ARG)
                 .help("prints length of results")
                 .long("length")
                 .short("L"),
         )
         .arg(
-            Arg::with_name(print_mode_arg)
+            Arg::with_name(PRINT_MODE_ARG)
                 .takes_value(true)
-                .help("what mode to print results in")
+                .help("what mode to print results in: v, p, pv or l")
                 .long("printMode")
                 .short("p"),
         )
         .arg(
-            Arg::with_name(collect_arg)
+            Arg::with_name(COLLECT_ARG)
                 .takes_value(false)
                 .help("collect results into an array")
                 .long("collect")
                 .short("C"),
         )
         .arg(
-            Arg::with_name(doc_idx_arg)
+            Arg::with_name(OUTPUT_ARG)
+                .takes_value(true)
+                .possible_values(&["yaml", "json", "json-compact"])
+                .help("format to print matching nodes in")
+                .long("output")
+                .short("o"),
+        )
+        .arg(
+            Arg::with_name(STREAM_ARG)
+                .help("search while parsing, without loading whole documents into memory")
+                .long("stream"),
+        )
+        .arg(
+            Arg::with_name(STRICT_ARG)
+                .help("fail on array indices out of range instead of skipping them")
+                .long("strict")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(QUIET_ARG)
+                .help("print nothing, only exit with status 0 if anything matched and 1 otherwise")
+                .long("quiet")
+                .short("q"),
+        )
+        .arg(
+            Arg::with_name(COUNT_ARG)
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .possible_values(&["total", "per-doc"])
+                .help("print the number of matches in total, with `--count=per-doc` also for each file and document")
+                .long("count"),
+        )
+        .arg(
+            Arg::with_name(FIRST_ARG)
+                .help("stop searching at the first match, the same as `--limit 1`")
+                .long("first")
+                .conflicts_with(LIMIT_ARG),
+        )
+        .arg(
+            Arg::with_name(LIMIT_ARG)
+                .takes_value(true)
+                .help("stop searching once this many matches have been found in total")
+                .long("limit"),
+        )
+        .arg(
+            Arg::with_name(JOBS_ARG)
+                .takes_value(true)
+                .help("number of threads searching files, or the documents of a single file")
+                .long("jobs")
+                .short("j"),
+        )
+        .arg(
+            Arg::with_name(DOC_IDX_ARG)
                 .takes_value(true)
                 .help("document index to search")
                 .long("docIndex")
-                .short("d"),
+                .short("d")
+                .global(true),
         )
         .arg(
-            Arg::with_name(debug_arg)
+            Arg::with_name(INPUT_ARG)
+                .takes_value(true)
+                .possible_values(&["yaml", "json"])
+                .help("format of the input file, json by default for `.json` files and yaml otherwise")
+                .long("input")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(DEBUG_ARG)
                 .help("enable debug logging")
                 .long("debug")
-                .short("v"),
+                .short("v")
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name(WRITE_SUBCOMMAND)
+                .about("sets the value of every node matching a path")
+                .args(&edit_args("path to write to"))
+                .arg(
+                    Arg::with_name(VALUE_ARG)
+                        .help("yaml value to write")
+                        .required(true)
+                        .index(3),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(DELETE_SUBCOMMAND)
+                .about("deletes every node matching a path")
+                .args(&edit_args("path to delete")),
         )
-        .get_matches();
+        .get_matches_safe()
+        .unwrap_or_else(|err| match err.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => err.exit(),
+            _ => {
+                eprintln!("{}", err.message);
+                std::process::exit(ERROR_EXIT_CODE);
+            }
+        });
 
-    let file_name = matches.value_of(yaml_file_arg).unwrap();
-    let path = matches.value_of(path_expression_arg).unwrap();
+    match matches.subcommand() {
+        (WRITE_SUBCOMMAND, Some(write_matches)) => run_write(write_matches),
+        (DELETE_SUBCOMMAND, Some(delete_matches)) => run_delete(delete_matches),
+        _ => {
+            let exit_code = run_search(&matches);
+            if exit_code != 0 {
+                std::process::exit(exit_code);
+            }
+        }
+    }
+}
 
-    let log_level = if matches.is_present(debug_arg) {
+/// Arguments shared by the subcommands that edit a yaml file.
+fn edit_args<'a, 'b>(path_help: &'a str) -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name(YAML_FILE_ARG)
+            .help("sets the input yaml file to use")
+            .required(true)
+            .index(1),
+        Arg::with_name(PATH_EXPRESSION_ARG)
+            .help(path_help)
+            .required(true)
+            .index(2),
+        Arg::with_name(IN_PLACE_ARG)
+            .help("update the yaml file in place")
+            .long("inPlace")
+            .short("i"),
+    ]
+}
+
+fn init_logger(matches: &ArgMatches) -> LevelFilter {
+    let log_level = if matches.is_present(DEBUG_ARG) {
         LevelFilter::Debug
     } else {
         LevelFilter::Error
     };
-    let _ = log::set_logger(&LOGGER)
+    log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(log_level))
         .unwrap_or_else(|err| {
             eprintln!("failed to set logger: `{}`", err);
         });
+    log_level
+}
+
+fn parse_path_or_exit(path: &str) -> Vec<PathSegment> {
+    let parsed_path = match parse_path(path) {
+        Ok(parsed_path) => parsed_path,
+        Err(e) => {
+            error!("failed to parse path, error: {}", e);
+            std::process::exit(ERROR_EXIT_CODE);
+        }
+    };
+    debug!("parsed path: {:?}", parsed_path);
+    parsed_path
+}
 
-    let docs_str = if file_name == "-" {
+fn read_input(file_name: &str) -> Result<String, String> {
+    if file_name == "-" {
         let mut buffer = String::new();
         io::stdin()
             .read_to_string(&mut buffer)
-            .unwrap_or_else(|err| {
-                error!("failed to read from stdin: `{}`", err);
-                std::process::exit(1);
-            });
-        buffer
+            .map_err(|err| format!("failed to read from stdin: `{}`", err))?;
+        Ok(buffer)
     } else {
-        std::fs::read_to_string(file_name).unwrap_or_else(|err| {
-            error!("failed to read file `{}`: `{}`", file_name, err);
-            std::process::exit(1);
-        })
-    };
-    let mut docs: &[Yaml] = &YamlLoader::load_from_str(&docs_str).unwrap_or_else(|err| {
-        error!("failed to load yaml file `{}`: `{}`", file_name, err);
-        std::process::exit(1);
-    });
+        std::fs::read_to_string(file_name)
+            .map_err(|err| format!("failed to read file `{}`: `{}`", file_name, err))
+    }
+}
+
+/// Loads the documents of a file, the documents of a yaml stream on up to `jobs` threads.
+fn load_docs(
+    docs_str: &str,
+    file_name: &str,
+    input_format: InputFormat,
+    jobs: usize,
+) -> Result<Vec<Yaml>, String> {
+    match input_format {
+        InputFormat::Yaml => load_docs_ordered(docs_str, jobs)
+            .map_err(|err| format!("failed to load yaml file `{}`: `{}`", file_name, err)),
+        InputFormat::Json => load_json(docs_str)
+            .map(|doc| vec![doc])
+            .map_err(|err| format!("failed to load json file `{}`: `{}`", file_name, err)),
+    }
+}
+
+/// Returns the document index requested with `--docIndex`, or `None` to use all documents.
+fn parse_doc_idx(matches: &ArgMatches) -> Option<usize> {
+    let doc_idx = matches.value_of(DOC_IDX_ARG)?;
+    if doc_idx == "*" {
+        return None;
+    }
+    match doc_idx.parse::<usize>() {
+        Ok(idx) => Some(idx),
+        Err(e) => {
+            error!(
+                "failed to parse document index `{}`, error: {:?}",
+                doc_idx, e
+            );
+            std::process::exit(ERROR_EXIT_CODE);
+        }
+    }
+}
 
+/// Returns the range of documents selected by `--docIndex`, or all documents when it is absent.
+fn select_docs(
+    matches: &ArgMatches,
+    num_docs: usize,
+    file_name: &str,
+) -> Result<Range<usize>, String> {
     // Multi document support, doc is a yaml::Yaml
-    if docs.len() == 0 {
-        error!("no yaml documents found in file `{}`", file_name);
-        std::process::exit(1);
-    } else if matches.is_present(doc_idx_arg) {
-        let doc_idx = matches.value_of(doc_idx_arg).unwrap();
-        if doc_idx == "*" {
+    check_num_docs(matches, num_docs, file_name)?;
+    match parse_doc_idx(matches) {
+        Some(doc_idx) => {
+            debug!(
+                "processing document at index `{}` in file `{}`",
+                doc_idx, file_name
+            );
+            Ok(doc_idx..doc_idx + 1)
+        }
+        None => {
             debug!(
                 "processing all `{}` documents in file `{}`",
-                docs.len(),
-                file_name
+                num_docs, file_name
             );
-        } else {
-            let parsed_doc_idx = match doc_idx.parse::<usize>() {
-                Ok(idx) => idx,
-                Err(e) => {
-                    error!(
-                        "failed to parse document index `{}`, error: {:?}",
-                        doc_idx, e
-                    );
-                    std::process::exit(1);
+            Ok(0..num_docs)
+        }
+    }
+}
+
+/// Fails if the file has no documents, or fewer than the index requested with `--docIndex`.
+fn check_num_docs(matches: &ArgMatches, num_docs: usize, file_name: &str) -> Result<(), String> {
+    if num_docs == 0 {
+        return Err(format!("no yaml documents found in file `{}`", file_name));
+    }
+    match parse_doc_idx(matches) {
+        Some(doc_idx) if doc_idx >= num_docs => Err(format!(
+            "only `{}` documents are present in file `{}`, but document index `{}` was requested for searching",
+            num_docs, file_name, doc_idx
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn run_write(matches: &ArgMatches) {
+    let value = parse_value(matches.value_of(VALUE_ARG).unwrap());
+    debug!("value to write: {:?}", value);
+    let options = edit_options(matches);
+    run_edit(matches, |doc, parsed_path| {
+        write_with(doc, parsed_path, &value, options)
+    });
+}
+
+fn run_delete(matches: &ArgMatches) {
+    let options = edit_options(matches);
+    run_edit(matches, |doc, parsed_path| {
+        delete_with(doc, parsed_path, options)
+    });
+}
+
+/// How `write` and `delete` find the nodes they edit, strict with `--strict`.
+fn edit_options(matches: &ArgMatches) -> TraverseOptions {
+    TraverseOptions {
+        strict: matches.is_present(STRICT_ARG),
+        ..TraverseOptions::default()
+    }
+}
+
+/// Applies `edit` to each selected document, then prints all documents or writes them back to
+/// the input file.
+fn run_edit<F>(matches: &ArgMatches, edit: F)
+where
+    F: Fn(&mut Yaml, &[PathSegment]) -> Result<(), Error>,
+{
+    let file_name = matches.value_of(YAML_FILE_ARG).unwrap();
+    let path = matches.value_of(PATH_EXPRESSION_ARG).unwrap();
+    let in_place = matches.is_present(IN_PLACE_ARG);
+
+    init_logger(matches);
+
+    if in_place && file_name == "-" {
+        error!("cannot update stdin in place");
+        std::process::exit(ERROR_EXIT_CODE);
+    }
+
+    let parsed_path = parse_path_or_exit(path);
+
+    let input_format = input_format(matches, file_name);
+    let docs_str = exit_on_err(read_input(file_name));
+    let original_docs = exit_on_err(load_docs(&docs_str, file_name, input_format, 1));
+    let mut docs = original_docs.clone();
+    // an empty file is edited as a single empty document
+    if docs.is_empty() {
+        docs.push(Yaml::Null);
+    }
+    let selected = exit_on_err(select_docs(matches, docs.len(), file_name));
+
+    for doc in &mut docs[selected] {
+        unwrap_or_exit(edit(doc, &parsed_path), file_name);
+    }
+
+    let out_str = match input_format {
+        // patch the input text rather than re-emitting it, so comments and formatting survive
+        InputFormat::Yaml => {
+            unwrap_or_exit(render_lossless(&docs_str, &original_docs, &docs), file_name)
+        }
+        // json has no comments to keep, so it is simply written out again
+        InputFormat::Json => {
+            let mut out_str = String::new();
+            for doc in &docs {
+                out_str.push_str(&unwrap_or_exit(convert_json(doc, false), file_name));
+                out_str.push('\n');
+            }
+            out_str
+        }
+    };
+    if in_place {
+        std::fs::write(file_name, out_str).unwrap_or_else(|err| {
+            error!("failed to write file `{}`: `{}`", file_name, err);
+            std::process::exit(ERROR_EXIT_CODE);
+        });
+    } else {
+        print!("{}", out_str);
+    }
+}
+
+/// Searches every input, returning the exit status. Like grep, a file that fails to be searched
+/// is reported and the search goes on, exiting with an error at the end unless a quiet search
+/// found a match.
+fn run_search(matches: &ArgMatches) -> i32 {
+    let log_level = init_logger(matches);
+    let (paths, inputs) = split_positionals(matches);
+
+    // parse paths once, up front, so a malformed path fails before any document is searched
+    let parsed_paths: Vec<Vec<PathSegment>> =
+        paths.iter().map(|path| parse_path_or_exit(path)).collect();
+    if matches.is_present(STREAM_ARG) && parsed_paths.len() > 1 {
+        error!("`--{}` searches for a single path expression", STREAM_ARG);
+        std::process::exit(ERROR_EXIT_CODE);
+    }
+    let jobs = parse_jobs(matches);
+
+    let files = expand_inputs(&inputs).unwrap_or_else(|err| {
+        error!("failed to expand inputs: {}", err);
+        std::process::exit(ERROR_EXIT_CODE);
+    });
+    // like grep, output is prefixed with the file name once more than one file may be searched
+    let with_file_name = files.len() > 1 || files != inputs;
+    // like grep, a quiet search is done at the first match
+    let quiet = matches.is_present(QUIET_ARG);
+    let mut limit = if quiet { Some(1) } else { parse_limit(matches) };
+    let mut found = false;
+    let mut failed = false;
+    let mut matched = 0;
+    if jobs > 1 && limit.is_some() {
+        debug!("searching on a single thread, to stop at the limit of matches");
+    }
+    if jobs > 1 && files.len() > 1 && limit.is_none() {
+        // each file's output is held back until every file before it has been printed
+        for_each_ordered(
+            files.len(),
+            jobs,
+            |idx| {
+                let mut printer = Printer::new(matches, &files[idx], with_file_name, true);
+                search_file(matches, &parsed_paths, &mut printer, log_level, 1).map(|()| printer)
+            },
+            |printer| {
+                match printer {
+                    Ok(mut printer) => {
+                        print!("{}", printer.take_output());
+                        found |= printer.found;
+                        matched += printer.matched;
+                    }
+                    Err(msg) => {
+                        error!("{}", msg);
+                        failed = true;
+                    }
                 }
-            };
-            if parsed_doc_idx >= docs.len() {
-                error!("only `{}` documents are present in file `{}`, but document index `{}` was requested for searching",
-                    docs.len(), file_name, parsed_doc_idx);
-                std::process::exit(1);
+                true
+            },
+        );
+    } else {
+        for file_name in &files {
+            let mut printer = Printer::new(matches, file_name, with_file_name, false);
+            printer.limit = limit;
+            if let Err(msg) = search_file(matches, &parsed_paths, &mut printer, log_level, jobs) {
+                error!("{}", msg);
+                failed = true;
+            }
+            found |= printer.found;
+            matched += printer.matched;
+            limit = printer.limit;
+            if printer.is_done() {
+                break;
             }
-            debug!(
-                "processing document at index `{}` in file `{}`",
-                parsed_doc_idx, file_name
-            );
-            docs = &docs[parsed_doc_idx..parsed_doc_idx + 1]
         }
+    }
+    match parse_count_mode(matches) {
+        _ if quiet => {}
+        Some(CountMode::Total) => println!("{}", matched),
+        Some(CountMode::PerDoc) => println!("total: {}", matched),
+        None => {}
+    }
+    if found && quiet {
+        0
+    } else if failed {
+        ERROR_EXIT_CODE
+    } else if found {
+        0
     } else {
+        NO_MATCH_EXIT_CODE
+    }
+}
+
+/// Splits the positional arguments into the paths to search for and the inputs to search. Like
+/// with grep the path comes first, and with `-e` every positional argument is an input. The
+/// older `<yaml_file> <path_expression>` order is recognised by its first argument being an
+/// existing file or `-` while the second is no existing file or directory.
+fn split_positionals<'a>(matches: &'a ArgMatches) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut positionals: Vec<&str> = [PATH_EXPRESSION_ARG, INPUTS_ARG]
+        .iter()
+        .filter_map(|arg| matches.values_of(arg))
+        .flatten()
+        .collect();
+    if matches.is_present(EXPRESSION_ARG) {
+        return (
+            matches.values_of(EXPRESSION_ARG).unwrap().collect(),
+            positionals,
+        );
+    }
+    let is_file = |arg: &str| arg == "-" || Path::new(arg).is_file();
+    if is_file(positionals[0]) && !Path::new(positionals[1]).exists() {
         debug!(
-            "processing all `{}` documents in file `{}`",
-            docs.len(),
-            file_name
+            "reading `{}` as the file and `{}` as the path",
+            positionals[0], positionals[1]
         );
+        positionals.swap(0, 1);
     }
+    let path = positionals.remove(0);
+    (vec![path], positionals)
+}
 
-    for ref doc in docs {
-        if log_level == LevelFilter::Debug {
-            debug_print_doc_structure(doc).unwrap_or_else(|err| {
-                error!(
-                    "unable to print display document from file `{}`: {}",
-                    file_name, err
-                );
-                std::process::exit(1);
-            });
+/// Returns the number of threads requested with `--jobs`, 1 when it is absent.
+fn parse_jobs(matches: &ArgMatches) -> usize {
+    let jobs = match matches.value_of(JOBS_ARG) {
+        Some(jobs) => jobs,
+        None => return 1,
+    };
+    match jobs.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => jobs,
+        _ => {
+            error!(
+                "failed to parse number of jobs `{}`, expected a positive integer",
+                jobs
+            );
+            std::process::exit(ERROR_EXIT_CODE);
         }
+    }
+}
 
-        // parse path
-        let parsed_path = match parse_path(path) {
-            Ok(parsed_path) => parsed_path,
-            Err(e) => {
-                error!("failed to parse path, error: {}", e);
-                std::process::exit(1);
-            }
-        };
-        debug!("parsed path: {:?}", parsed_path);
+/// Returns the number of matches to stop at, given with `--first` or `--limit`.
+fn parse_limit(matches: &ArgMatches) -> Option<usize> {
+    if matches.is_present(FIRST_ARG) {
+        return Some(1);
+    }
+    let limit = matches.value_of(LIMIT_ARG)?;
+    match limit.parse::<usize>() {
+        Ok(limit) if limit > 0 => Some(limit),
+        _ => {
+            error!(
+                "failed to parse limit `{}`, expected a positive integer",
+                limit
+            );
+            std::process::exit(ERROR_EXIT_CODE);
+        }
+    }
+}
 
-        let mut visited = Vec::<VisitedNode>::new();
-        traverse(doc, "", &parsed_path, String::new(), false, &mut visited);
+/// Searches a file, loaded or streamed, followed by its number of matches with
+/// `--count=per-doc`.
+fn search_file(
+    matches: &ArgMatches,
+    parsed_paths: &[Vec<PathSegment>],
+    printer: &mut Printer,
+    log_level: LevelFilter,
+    jobs: usize,
+) -> Result<(), String> {
+    if matches.is_present(STREAM_ARG) {
+        run_stream_search(matches, &parsed_paths[0], printer)?;
+    } else {
+        search_docs(matches, parsed_paths, printer, log_level, jobs)?;
+    }
+    if printer.count == Some(CountMode::PerDoc) {
+        printer.print(&format!("{}: {}\n", printer.file_name, printer.matched));
+    }
+    Ok(())
+}
 
-        let default_yml: Yaml;
-        let default_visited_node: VisitedNode;
-        if visited.len() == 0 && matches.is_present(default_value_arg) {
-            let dv = matches.value_of(default_value_arg).unwrap();
-            debug!("found no matches, using default value `{}`", dv);
-            default_yml = Yaml::from_str(dv);
-            default_visited_node = VisitedNode {
-                yml: &default_yml,
-                path: "".to_string(),
-            };
-            visited.push(default_visited_node);
+/// Searches every selected document of a file for each of the paths in turn, until the
+/// printer's limit is reached. Documents are loaded on `jobs` threads, and without a limit also
+/// searched on them, with their output printed in document order.
+fn search_docs(
+    matches: &ArgMatches,
+    parsed_paths: &[Vec<PathSegment>],
+    printer: &mut Printer,
+    log_level: LevelFilter,
+    jobs: usize,
+) -> Result<(), String> {
+    let file_name = printer.file_name;
+    let strict = matches.is_present(STRICT_ARG);
+    let docs_str = read_input(file_name)?;
+    let all_docs = load_docs(&docs_str, file_name, input_format(matches, file_name), jobs)?;
+    let selected = select_docs(matches, all_docs.len(), file_name)?;
+    let first_idx = selected.start;
+    let docs = &all_docs[selected];
+    // finding where nodes start parses the input again, so only do it when they are printed
+    let source_map = match printer.print_mode {
+        PrintMode::Location if printer.count.is_none() => {
+            Some(SourceMap::new(&docs_str, &all_docs).map_err(|err| file_error(file_name, err))?)
         }
-        debug!("matched values: {:?}", visited);
+        _ => None,
+    };
 
-        let print_mode = parse_print_mode(matches.value_of(print_mode_arg).unwrap_or("v"));
-        debug!("print_mode: {:?}", print_mode);
+    let search_doc = |idx: usize, printer: &mut Printer| -> Result<(), Error> {
+        let doc = &docs[idx];
+        if log_level == LevelFilter::Debug {
+            debug_print_doc_structure(doc)?;
+        }
 
-        let collect = matches.is_present(collect_arg);
-        debug!("collect: {}", collect);
+        // every path is searched in the document parsed once, with its results kept together
+        let mut doc_matched = 0;
+        for parsed_path in parsed_paths {
+            if printer.is_done() {
+                break;
+            }
+            let mut visited = Vec::<VisitedNode>::new();
+            let options = TraverseOptions {
+                strict,
+                limit: printer.limit,
+            };
+            traverse_with(doc, parsed_path, options, &mut visited)?;
+            printer.count_matches(visited.len());
+            doc_matched += visited.len();
+            if let Some(source_map) = &source_map {
+                source_map.mark(&mut visited);
+            }
+            debug!("matched values: {:?}", visited);
 
-        if matches.is_present(length_arg) {
-            // length mode
-            if collect {
-                // length and collect just prints the number of visited nodes
-                println!("{}", visited.len());
-            } else {
-                match print_mode {
-                    PrintMode::Path => {
-                        for value in visited {
-                            println!("{}", value.path);
-                        }
-                    }
-                    PrintMode::Value => {
-                        for value in visited {
-                            println!("{}", convert_length(value.yml));
-                        }
+            if printer.count.is_some() {
+                continue;
+            }
+            if printer.counts_only() {
+                printer.print_count(visited.len());
+                continue;
+            }
+            for value in &visited {
+                printer.print_node(value.yml, &value.path, value.marker)?;
+            }
+            printer.finish_doc(visited.len())?;
+        }
+        printer.print_doc_count(first_idx + idx, doc_matched);
+        Ok(())
+    };
+
+    if jobs > 1 && docs.len() > 1 && printer.limit.is_none() {
+        let with_file_name = printer.with_file_name;
+        let mut searched = Ok(());
+        for_each_ordered(
+            docs.len(),
+            jobs,
+            |idx| {
+                let mut doc_printer = Printer::new(matches, file_name, with_file_name, true);
+                search_doc(idx, &mut doc_printer).map(|()| doc_printer)
+            },
+            |doc_printer| match doc_printer {
+                Ok(mut doc_printer) => {
+                    printer.print(&doc_printer.take_output());
+                    printer.found |= doc_printer.found;
+                    printer.matched += doc_printer.matched;
+                    true
+                }
+                Err(err) => {
+                    searched = Err(err);
+                    false
+                }
+            },
+        );
+        searched.map_err(|err| file_error(file_name, err))
+    } else {
+        (0..docs.len())
+            .try_for_each(|idx| search_doc(idx, printer))
+            .map_err(|err| file_error(file_name, err))
+    }
+}
+
+/// Searches the input straight from parser events, printing matches as they are found. Matches
+/// that are only counted are never built.
+fn run_stream_search(
+    matches: &ArgMatches,
+    parsed_path: &[PathSegment],
+    printer: &mut Printer,
+) -> Result<(), String> {
+    let file_name = printer.file_name;
+    let doc_idx = parse_doc_idx(matches);
+    let options = TraverseOptions {
+        strict: matches.is_present(STRICT_ARG),
+        limit: printer.limit,
+    };
+    let reader: Box<dyn BufRead> = if file_name == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        let file = File::open(file_name)
+            .map_err(|err| format!("failed to read file `{}`: `{}`", file_name, err))?;
+        Box::new(BufReader::new(file))
+    };
+    let mut chars = ReadChars::new(reader);
+    let num_docs = if printer.count.is_some() {
+        stream_count(&mut chars, parsed_path, doc_idx, options, |idx, matched| {
+            printer.count_matches(matched);
+            printer.print_doc_count(idx, matched);
+            Ok(())
+        })
+    } else {
+        let mut matched = 0;
+        let on_event = |event| {
+            match event {
+                StreamEvent::DocumentStart(_) => matched = 0,
+                StreamEvent::Match(node) => {
+                    matched += 1;
+                    printer.count_matches(1);
+                    if !printer.counts_only() {
+                        printer.print_node(&node.yml, &node.path, node.marker)?;
                     }
-                    PrintMode::ValueAndPath => {
-                        for value in visited {
-                            println!("{}: {}", value.path, convert_length(value.yml));
-                        }
+                }
+                StreamEvent::DocumentEnd(_) => {
+                    if printer.counts_only() {
+                        printer.print_count(matched);
+                    } else {
+                        printer.finish_doc(matched)?;
                     }
                 }
             }
+            Ok(())
+        };
+        stream_search(&mut chars, parsed_path, doc_idx, options, on_event)
+    };
+    if let Some(err) = chars.error {
+        return Err(format!("failed to read file `{}`: `{}`", file_name, err));
+    }
+    let num_docs = num_docs.map_err(|err| file_error(file_name, err))?;
+    check_num_docs(matches, num_docs, file_name)
+}
+
+/// Prints matches the way the search flags ask for.
+struct Printer<'a> {
+    file_name: &'a str,
+    // prefix every line with the file name, except locations that already start with it
+    with_file_name: bool,
+    print_mode: PrintMode,
+    length: bool,
+    collect: bool,
+    default_value: Option<&'a str>,
+    output_format: OutputFormat,
+    // json output of the current document that is printed as a whole once it is done
+    pending_json: Vec<(String, Yaml)>,
+    // output held back to be printed in order with that of other files or documents
+    output: Option<String>,
+    quiet: bool,
+    // whether anything matched, or a default value was printed instead
+    found: bool,
+    // matches still to be found before the search stops
+    limit: Option<usize>,
+    // matches found so far
+    matched: usize,
+    count: Option<CountMode>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(
+        matches: &'a ArgMatches,
+        file_name: &'a str,
+        with_file_name: bool,
+        hold_output: bool,
+    ) -> Printer<'a> {
+        let print_mode = parse_print_mode(matches.value_of(PRINT_MODE_ARG).unwrap_or("v"));
+        debug!("print_mode: {:?}", print_mode);
+        let collect = matches.is_present(COLLECT_ARG);
+        debug!("collect: {}", collect);
+        let output_format = parse_output_format(matches.value_of(OUTPUT_ARG).unwrap_or("yaml"));
+        debug!("output_format: {:?}", output_format);
+        Printer {
+            file_name,
+            with_file_name: with_file_name && print_mode != PrintMode::Location,
+            print_mode,
+            length: matches.is_present(LENGTH_ARG),
+            collect,
+            default_value: matches.value_of(DEFAULT_VALUE_ARG),
+            output_format,
+            pending_json: Vec::new(),
+            output: if hold_output {
+                Some(String::new())
+            } else {
+                None
+            },
+            quiet: matches.is_present(QUIET_ARG),
+            found: false,
+            limit: None,
+            matched: 0,
+            count: parse_count_mode(matches),
+        }
+    }
+
+    /// Whether the limit of matches has been reached, so nothing else should be searched.
+    fn is_done(&self) -> bool {
+        self.limit == Some(0)
+    }
+
+    fn count_matches(&mut self, matched: usize) {
+        self.matched += matched;
+        self.found |= matched > 0;
+        if let Some(limit) = &mut self.limit {
+            *limit -= matched;
+        }
+    }
+
+    /// Prints the number of matches in a document with `--count=per-doc`.
+    fn print_doc_count(&mut self, doc_idx: usize, matched: usize) {
+        if self.count == Some(CountMode::PerDoc) {
+            self.print(&format!("{}:{}: {}\n", self.file_name, doc_idx, matched));
+        }
+    }
+
+    /// Length and collect together just print the number of matches in each document.
+    fn counts_only(&self) -> bool {
+        self.length && self.collect
+    }
+
+    /// Prints `out`, with each of its lines prefixed by the file name when searching many files.
+    fn println(&mut self, out: String) {
+        let mut text = String::new();
+        if self.with_file_name {
+            for line in out.split('\n') {
+                text.push_str(&format!("{}:{}\n", self.file_name, line));
+            }
         } else {
-            // no length mode
-            let collect_prepend = if collect { "- " } else { "" };
-            match print_mode {
-                PrintMode::Path => {
-                    for value in visited {
-                        println!("{}{}", collect_prepend, value.path);
-                    }
+            text.push_str(&out);
+            text.push('\n');
+        }
+        self.print(&text);
+    }
+
+    /// Prints `text` as it is, or holds it back until `take_output`.
+    fn print(&mut self, text: &str) {
+        if self.quiet {
+            return;
+        }
+        match &mut self.output {
+            Some(output) => output.push_str(text),
+            None => print!("{}", text),
+        }
+    }
+
+    fn take_output(&mut self) -> String {
+        self.output.take().unwrap_or_default()
+    }
+
+    fn print_count(&mut self, matched: usize) {
+        self.found |= matched > 0 || self.default_value.is_some();
+        if matched == 0 && self.default_value.is_some() {
+            self.println("1".to_string());
+        } else {
+            self.println(matched.to_string());
+        }
+    }
+
+    /// Prefix for `PrintMode::Location`, `file:line:col` like grep with a 1-based column. Nodes
+    /// without a marker, like the default value, only get the file name.
+    fn location(&self, marker: Option<Marker>) -> String {
+        match marker {
+            Some(marker) => format!("{}:{}:{}", self.file_name, marker.line, marker.col + 1),
+            None => self.file_name.to_string(),
+        }
+    }
+
+    fn print_node(&mut self, yml: &Yaml, path: &str, marker: Option<Marker>) -> Result<(), Error> {
+        if self.output_format != OutputFormat::Yaml {
+            return self.print_json_node(yml, path, marker);
+        }
+        if self.length {
+            match self.print_mode {
+                PrintMode::Path => self.println(path.to_string()),
+                PrintMode::Value => self.println(convert_length(yml)?),
+                PrintMode::ValueAndPath => {
+                    self.println(format!("{}: {}", path, convert_length(yml)?))
                 }
+                PrintMode::Location => self.println(format!(
+                    "{}: {}: {}",
+                    self.location(marker),
+                    path,
+                    convert_length(yml)?
+                )),
+            }
+        } else {
+            let collect_prepend = if self.collect { "- " } else { "" };
+            match self.print_mode {
+                PrintMode::Path => self.println(format!("{}{}", collect_prepend, path)),
                 PrintMode::Value => {
-                    for value in visited {
-                        println!("{}{}", collect_prepend, convert_single_node(value.yml));
-                    }
+                    self.println(format!("{}{}", collect_prepend, convert_single_node(yml)?))
                 }
-                PrintMode::ValueAndPath => {
-                    for value in visited {
-                        println!(
-                            "{}{}: {}",
-                            collect_prepend,
-                            value.path,
-                            convert_single_node(value.yml)
-                        );
-                    }
+                PrintMode::ValueAndPath => self.println(format!(
+                    "{}{}: {}",
+                    collect_prepend,
+                    path,
+                    convert_single_node(yml)?
+                )),
+                PrintMode::Location => self.println(format!(
+                    "{}{}: {}: {}",
+                    collect_prepend,
+                    self.location(marker),
+                    path,
+                    convert_single_node(yml)?
+                )),
+            }
+        }
+        Ok(())
+    }
+
+    /// Collected results are printed as one json array per document, and paths with values as
+    /// one json object keyed by path.
+    fn json_per_doc(&self) -> bool {
+        self.collect || self.print_mode == PrintMode::ValueAndPath
+    }
+
+    fn print_json_node(
+        &mut self,
+        yml: &Yaml,
+        path: &str,
+        marker: Option<Marker>,
+    ) -> Result<(), Error> {
+        let value = if self.length {
+            Yaml::from_str(&convert_length(yml)?)
+        } else {
+            yml.clone()
+        };
+        let item = match self.print_mode {
+            PrintMode::Path => Yaml::String(path.to_string()),
+            PrintMode::Value | PrintMode::ValueAndPath => value,
+            PrintMode::Location => {
+                let mut location = Hash::new();
+                location.insert(
+                    Yaml::String("file".to_string()),
+                    Yaml::String(self.file_name.to_string()),
+                );
+                if let Some(marker) = marker {
+                    location.insert(
+                        Yaml::String("line".to_string()),
+                        Yaml::Integer(marker.line as i64),
+                    );
+                    location.insert(
+                        Yaml::String("col".to_string()),
+                        Yaml::Integer(marker.col as i64 + 1),
+                    );
+                }
+                location.insert(
+                    Yaml::String("path".to_string()),
+                    Yaml::String(path.to_string()),
+                );
+                location.insert(Yaml::String("value".to_string()), value);
+                Yaml::Hash(location)
+            }
+        };
+        if self.json_per_doc() {
+            self.pending_json.push((path.to_string(), item));
+            Ok(())
+        } else {
+            self.print_json(&item)
+        }
+    }
+
+    /// Prints a json node. When searching many files it is wrapped in an object along with the
+    /// name of its file, as prefixing each of its lines would no longer be json.
+    fn print_json(&mut self, node: &Yaml) -> Result<(), Error> {
+        let compact = self.output_format == OutputFormat::JsonCompact;
+        if !self.with_file_name {
+            self.println(convert_json(node, compact)?);
+            return Ok(());
+        }
+        let mut with_file = Hash::new();
+        with_file.insert(
+            Yaml::String("file".to_string()),
+            Yaml::String(self.file_name.to_string()),
+        );
+        with_file.insert(Yaml::String("value".to_string()), node.clone());
+        let json = convert_json(&Yaml::Hash(with_file), compact)?;
+        self.print(&format!("{}\n", json));
+        Ok(())
+    }
+
+    /// Prints the default value if nothing in the document matched, and any json waiting for
+    /// the document to be done.
+    fn finish_doc(&mut self, matched: usize) -> Result<(), Error> {
+        self.found |= matched > 0 || self.default_value.is_some();
+        if let (0, Some(dv)) = (matched, self.default_value) {
+            debug!("found no matches, using default value `{}`", dv);
+            self.print_node(&Yaml::from_str(dv), "", None)?;
+        }
+        if self.output_format != OutputFormat::Yaml && self.json_per_doc() {
+            let pending = std::mem::take(&mut self.pending_json);
+            let doc_json = if self.print_mode == PrintMode::ValueAndPath {
+                Yaml::Hash(
+                    pending
+                        .into_iter()
+                        .map(|(path, item)| (Yaml::String(path), item))
+                        .collect(),
+                )
+            } else {
+                Yaml::Array(pending.into_iter().map(|(_, item)| item).collect())
+            };
+            self.print_json(&doc_json)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads chars from a buffered reader one line at a time, so the input is never held in memory
+/// all at once. A read error ends the chars and is kept in `error`.
+struct ReadChars<R: BufRead> {
+    reader: R,
+    line: String,
+    pos: usize,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> ReadChars<R> {
+    fn new(reader: R) -> ReadChars<R> {
+        ReadChars {
+            reader,
+            line: String::new(),
+            pos: 0,
+            error: None,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ReadChars<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.pos >= self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => {
+                    self.error = Some(err);
+                    return None;
                 }
             }
         }
+        let c = self.line[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
     }
 }
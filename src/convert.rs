@@ -1,64 +1,201 @@
-use log::{debug, error};
-use yaml_rust::emitter::{EmitError, EmitResult};
+use crate::error::Error;
+use log::debug;
 use yaml_rust::{Yaml, YamlEmitter};
 
-pub fn debug_print_doc_structure(doc: &Yaml) -> EmitResult {
+pub fn debug_print_doc_structure(doc: &Yaml) -> Result<(), Error> {
     let out_str = get_node_structure(doc)?;
     debug!("doc structure:\n{}", out_str);
     Ok(())
 }
 
-fn get_node_structure(node: &Yaml) -> Result<String, EmitError> {
+pub(crate) fn get_node_structure(node: &Yaml) -> Result<String, Error> {
     let mut out_str = String::new();
     let mut emitter = YamlEmitter::new(&mut out_str);
     emitter.dump(node)?;
     // remove initial four characters ("---\n") from the node
     if out_str.len() < 4 {
-        error!("invalid node structure `{:?}`", node);
-        std::process::exit(1);
+        return Err(Error::Emit(format!("invalid node structure `{:?}`", node)));
     }
     Ok(out_str.trim_start_matches("---\n").to_string())
 }
 
-pub fn convert_single_node(node: &Yaml) -> String {
+/// Emits a whole stream of documents, separating them with `---`.
+pub fn convert_docs(docs: &[Yaml]) -> Result<String, Error> {
+    let mut out_str = String::new();
+    for (idx, doc) in docs.iter().enumerate() {
+        if idx > 0 {
+            out_str.push_str("---\n");
+        }
+        out_str.push_str(&get_node_structure(doc)?);
+        out_str.push('\n');
+    }
+    Ok(out_str)
+}
+
+pub fn convert_single_node(node: &Yaml) -> Result<String, Error> {
+    match node {
+        Yaml::String(s) => Ok(s.to_string()),
+        Yaml::Integer(i) => Ok(i.to_string()),
+        Yaml::Real(f) => Ok(f.to_string()),
+        Yaml::Boolean(b) => Ok(b.to_string()),
+        h @ Yaml::Hash(_) => get_node_structure(h),
+        v @ Yaml::Array(_) => get_node_structure(v),
+        Yaml::Null => Ok("null".to_string()),
+        Yaml::BadValue => Ok(format!("node `{:?}` is corrupted", node)),
+        Yaml::Alias(_) => Err(Error::UnsupportedNode(node.clone())),
+    }
+}
+
+pub fn convert_length(node: &Yaml) -> Result<String, Error> {
+    match node {
+        Yaml::String(s) => Ok(s.len().to_string()),
+        Yaml::Hash(h) => Ok(h.len().to_string()),
+        Yaml::Array(a) => Ok(a.len().to_string()),
+        Yaml::Integer(i) => Ok(i.to_string().len().to_string()),
+        Yaml::Real(f) => Ok(f.len().to_string()),
+        Yaml::Boolean(b) => Ok(b.to_string().len().to_string()),
+        Yaml::Null => Ok("0".to_string()),
+        Yaml::BadValue => Ok(format!("node `{:?}` is corrupted", node)),
+        Yaml::Alias(_) => Err(Error::UnsupportedNode(node.clone())),
+    }
+}
+
+/// Emits a node as json, pretty printed with two space indentation unless `compact` is set.
+/// Map keys have to be scalars, and reals json can't represent (e.g. `.inf`) become `null`.
+pub fn convert_json(node: &Yaml, compact: bool) -> Result<String, Error> {
+    let mut out_str = String::new();
+    emit_json(node, compact, 0, &mut out_str)?;
+    Ok(out_str)
+}
+
+fn emit_json(node: &Yaml, compact: bool, indent: usize, out_str: &mut String) -> Result<(), Error> {
     match node {
-        Yaml::String(s) => format!("{}", s),
-        Yaml::Integer(i) => format!("{}", i),
-        Yaml::Real(f) => format!("{}", f),
-        Yaml::Boolean(b) => format!("{}", b),
-        h @ Yaml::Hash(_) => {
-            let s = get_node_structure(h).unwrap_or_else(|err| {
-                error!("failed to convert map value `{:?}` to string: {}", h, err);
-                std::process::exit(1);
-            });
-            format!("{}", s)
+        Yaml::String(s) => emit_json_string(s, out_str),
+        Yaml::Integer(i) => out_str.push_str(&i.to_string()),
+        Yaml::Real(f) => out_str.push_str(&json_number(f, node)),
+        Yaml::Boolean(b) => out_str.push_str(&b.to_string()),
+        Yaml::Null | Yaml::BadValue => out_str.push_str("null"),
+        Yaml::Hash(h) => {
+            let mut entries = Vec::with_capacity(h.len());
+            for (k, v) in h {
+                let mut entry = String::new();
+                emit_json_string(&json_key(k)?, &mut entry);
+                entry.push_str(if compact { ":" } else { ": " });
+                emit_json(v, compact, indent + 1, &mut entry)?;
+                entries.push(entry);
+            }
+            emit_json_collection(entries, ('{', '}'), compact, indent, out_str);
         }
-        v @ Yaml::Array(_) => {
-            let s = get_node_structure(v).unwrap_or_else(|err| {
-                error!("failed to convert array value `{:?}` to string: {}", v, err);
-                std::process::exit(1);
-            });
-            format!("{}", s)
+        Yaml::Array(a) => {
+            let mut items = Vec::with_capacity(a.len());
+            for v in a {
+                let mut item = String::new();
+                emit_json(v, compact, indent + 1, &mut item)?;
+                items.push(item);
+            }
+            emit_json_collection(items, ('[', ']'), compact, indent, out_str);
         }
-        Yaml::Null => format!("null"),
-        Yaml::BadValue => format!("node `{:?}` is corrupted", node),
-        Yaml::Alias(_) => {
-            panic!("alias type not implemented");
+        Yaml::Alias(_) => return Err(Error::UnsupportedNode(node.clone())),
+    }
+    Ok(())
+}
+
+fn emit_json_collection(
+    items: Vec<String>,
+    (open, close): (char, char),
+    compact: bool,
+    indent: usize,
+    out_str: &mut String,
+) {
+    out_str.push(open);
+    if compact {
+        out_str.push_str(&items.join(","));
+    } else if !items.is_empty() {
+        let item_indent = "  ".repeat(indent + 1);
+        for (idx, item) in items.iter().enumerate() {
+            out_str.push_str(if idx == 0 { "\n" } else { ",\n" });
+            out_str.push_str(&item_indent);
+            out_str.push_str(item);
         }
+        out_str.push('\n');
+        out_str.push_str(&"  ".repeat(indent));
     }
+    out_str.push(close);
 }
 
-pub fn convert_length(node: &Yaml) -> String {
-    match node {
-        Yaml::String(s) => format!("{}", s.len()),
-        Yaml::Hash(h) => format!("{}", h.len()),
-        Yaml::Array(a) => format!("{}", a.len()),
-        Yaml::Integer(i) => format!("{}", i.to_string().len()),
-        Yaml::Real(f) => format!("{}", f.to_string().len()),
-        Yaml::Boolean(b) => format!("{}", b.to_string().len()),
-        Yaml::Null => format!("0"),
-        Yaml::BadValue => format!("node `{:?}` is corrupted", node),
-        _a @ Yaml::Alias(_) => panic!("alias type node yet implemented"),
+fn emit_json_string(s: &str, out_str: &mut String) {
+    out_str.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out_str.push_str("\\\""),
+            '\\' => out_str.push_str("\\\\"),
+            '\n' => out_str.push_str("\\n"),
+            '\r' => out_str.push_str("\\r"),
+            '\t' => out_str.push_str("\\t"),
+            '\u{8}' => out_str.push_str("\\b"),
+            '\u{c}' => out_str.push_str("\\f"),
+            c if (c as u32) < 0x20 => out_str.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out_str.push(c),
+        }
+    }
+    out_str.push('"');
+}
+
+/// Json object keys are strings, so scalar keys are written the way they appear in yaml.
+fn json_key(key: &Yaml) -> Result<String, Error> {
+    match key {
+        Yaml::String(s) | Yaml::Real(s) => Ok(s.to_string()),
+        Yaml::Integer(i) => Ok(i.to_string()),
+        Yaml::Boolean(b) => Ok(b.to_string()),
+        Yaml::Null => Ok("null".to_string()),
+        _ => Err(Error::UnsupportedKey(key.clone())),
+    }
+}
+
+/// Yaml reals such as `+1.5` or `.5` aren't valid json numbers, so they are written from their
+/// parsed value instead.
+fn json_number(f: &str, node: &Yaml) -> String {
+    let is_json_number = {
+        let digits = f.strip_prefix('-').unwrap_or(f);
+        let (int_part, rest) = digits.split_at(
+            digits
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(digits.len()),
+        );
+        let valid_int = int_part == "0" || (!int_part.is_empty() && !int_part.starts_with('0'));
+        let valid_rest = match rest.strip_prefix('.') {
+            Some(frac) => {
+                let frac_digits = frac
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(frac.len());
+                frac_digits > 0 && valid_exponent(&frac[frac_digits..])
+            }
+            None => valid_exponent(rest),
+        };
+        valid_int && valid_rest
+    };
+    if is_json_number {
+        return f.to_string();
+    }
+    match node.as_f64() {
+        Some(parsed) if parsed.is_finite() => format!("{:?}", parsed),
+        _ => {
+            debug!("real `{}` can't be represented in json, using null", f);
+            "null".to_string()
+        }
+    }
+}
+
+fn valid_exponent(exponent: &str) -> bool {
+    if exponent.is_empty() {
+        return true;
+    }
+    match exponent.strip_prefix(['e', 'E']) {
+        Some(exp) => {
+            let exp = exp.strip_prefix(['+', '-']).unwrap_or(exp);
+            !exp.is_empty() && exp.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
     }
 }
 
@@ -73,4 +210,55 @@ mod tests {
             "node structure"
         );
     }
+
+    #[test]
+    fn test_convert_json_scalars() {
+        assert_eq!(
+            convert_json(&Yaml::String("a \"quoted\"\n\u{1}".to_string()), false).unwrap(),
+            "\"a \\\"quoted\\\"\\n\\u0001\""
+        );
+        assert_eq!(convert_json(&Yaml::Integer(-3), false).unwrap(), "-3");
+        assert_eq!(convert_json(&Yaml::Boolean(true), false).unwrap(), "true");
+        assert_eq!(convert_json(&Yaml::Null, false).unwrap(), "null");
+    }
+
+    #[test]
+    fn test_convert_json_reals() {
+        for (real, json) in &[
+            ("1.5", "1.5"),
+            ("-0.25e-3", "-0.25e-3"),
+            ("+1.5", "1.5"),
+            (".5", "0.5"),
+            ("1.", "1.0"),
+            ("1e3", "1e3"),
+            (".inf", "null"),
+            (".NaN", "null"),
+        ] {
+            assert_eq!(
+                convert_json(&Yaml::Real(real.to_string()), true).unwrap(),
+                *json,
+                "real `{}`",
+                real
+            );
+        }
+    }
+
+    #[test]
+    fn test_convert_json_collections() {
+        let doc = &yaml_rust::YamlLoader::load_from_str("a: [1, {b: x}]\n1: []\nc: {}").unwrap()[0];
+        assert_eq!(
+            convert_json(doc, true).unwrap(),
+            "{\"a\":[1,{\"b\":\"x\"}],\"1\":[],\"c\":{}}"
+        );
+        assert_eq!(
+            convert_json(doc, false).unwrap(),
+            "{\n  \"a\": [\n    1,\n    {\n      \"b\": \"x\"\n    }\n  ],\n  \"1\": [],\n  \"c\": {}\n}"
+        );
+    }
+
+    #[test]
+    fn test_convert_json_complex_key() {
+        let doc = &yaml_rust::YamlLoader::load_from_str("? [a]\n: 1").unwrap()[0];
+        assert!(convert_json(doc, false).is_err());
+    }
 }
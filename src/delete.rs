@@ -0,0 +1,95 @@
+use crate::error::Error;
+use crate::path::PathSegment;
+use crate::traverse::{traverse_mut_with, TraverseOptions, VisitAction};
+use log::debug;
+use yaml_rust::Yaml;
+
+/// Removes every map entry and array element in `doc` matching `path`. Array elements are removed
+/// back to front, so the indices of matches within the same array stay valid while removing.
+/// Deleting the document root leaves a null document.
+pub fn delete(doc: &mut Yaml, path: &[PathSegment]) -> Result<(), Error> {
+    delete_with(doc, path, TraverseOptions::default())
+}
+
+/// Like `delete`, with `options`, so that when strict an index past the end of its array is an
+/// error instead of deleting nothing.
+pub fn delete_with(
+    doc: &mut Yaml,
+    path: &[PathSegment],
+    options: TraverseOptions,
+) -> Result<(), Error> {
+    traverse_mut_with(doc, path, options, &mut |node, node_path| {
+        debug!("deleting node at `{}`: {:?}", node_path, node);
+        VisitAction::Remove
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::convert_single_node;
+    use crate::path::parse_path;
+    use yaml_rust::YamlLoader;
+
+    fn delete_str(doc_str: &str, path: &str) -> String {
+        let mut doc = YamlLoader::load_from_str(doc_str).unwrap().remove(0);
+        delete(&mut doc, &parse_path(path).unwrap()).unwrap();
+        convert_single_node(&doc).unwrap()
+    }
+
+    #[test]
+    fn test_delete_hash_entry() {
+        assert_eq!(delete_str("a:\n  b: 1\n  c: 2", "a.b"), "a:\n  c: 2");
+    }
+
+    #[test]
+    fn test_delete_missing() {
+        assert_eq!(delete_str("a:\n  b: 1", "a.c"), "a:\n  b: 1");
+    }
+
+    #[test]
+    fn test_delete_array_indices() {
+        assert_eq!(delete_str("- a\n- b\n- c", "[1]"), "- a\n- c");
+        assert_eq!(delete_str("- a\n- b\n- c", "[*]"), "[]");
+    }
+
+    #[test]
+    fn test_delete_child_filter() {
+        assert_eq!(
+            delete_str("- name: a\n- name: b\n- name: a", "(name==a)"),
+            "- name: b"
+        );
+        assert_eq!(delete_str("a: crab\nb: fish", "(.==crab)"), "b: fish");
+    }
+
+    #[test]
+    fn test_delete_splat() {
+        assert_eq!(
+            delete_str("a:\n  b: 1\n  c:\n    - b: 2\n    - d: 3", "**.b"),
+            "a:\n  c:\n    - {}\n    - d: 3"
+        );
+    }
+
+    #[test]
+    fn test_delete_strict() {
+        let strict = TraverseOptions {
+            strict: true,
+            ..TraverseOptions::default()
+        };
+        let mut doc = YamlLoader::load_from_str("a: [1, 2]").unwrap().remove(0);
+        assert_eq!(
+            delete_with(&mut doc, &parse_path("a[2]").unwrap(), strict),
+            Err(Error::IndexOutOfRange {
+                path: "a[2]".to_string(),
+                len: 2
+            })
+        );
+        delete_with(&mut doc, &parse_path("a[1]").unwrap(), strict).unwrap();
+        assert_eq!(convert_single_node(&doc).unwrap(), "a:\n  - 1");
+    }
+
+    #[test]
+    fn test_delete_root() {
+        assert_eq!(delete_str("a: 1", ""), "null");
+    }
+}
@@ -0,0 +1,97 @@
+use crate::json::JsonError;
+use crate::path::ParseError;
+use std::fmt;
+use yaml_rust::emitter::EmitError;
+use yaml_rust::scanner::ScanError;
+use yaml_rust::Yaml;
+
+/// Errors returned by the library. Callers decide whether an error is fatal; only `run_cli`
+/// exits the process.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// an invalid path expression
+    Parse(ParseError),
+    /// a map key that is neither a string nor an integer
+    UnsupportedKey(Yaml),
+    /// a node type that ry cannot handle, e.g. an unresolved alias
+    UnsupportedNode(Yaml),
+    /// a node that could not be emitted as yaml
+    Emit(String),
+    /// input that is not valid yaml
+    Scan(ScanError),
+    /// input that is not valid json
+    Json(JsonError),
+    /// an array index past the end of the array it selects from, only an error when strict
+    IndexOutOfRange { path: String, len: usize },
+    /// an input on the command line that could not be expanded into files to search
+    Input(String),
+    /// a write that would have to create `child` beneath a node of another type
+    CannotCreate {
+        child: String,
+        node_type: &'static str,
+        path: String,
+    },
+    /// a write that would have to grow an array past the largest array it creates
+    IndexTooLarge {
+        index: usize,
+        max: usize,
+        path: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(e) => write!(f, "{}", e),
+            Error::UnsupportedKey(k) => write!(
+                f,
+                "key `{:?}` is not valid, only string and integer keys are supported",
+                k
+            ),
+            Error::UnsupportedNode(n) => write!(f, "node `{:?}` is not supported", n),
+            Error::Emit(e) => write!(f, "failed to emit yaml: {}", e),
+            Error::Scan(e) => write!(f, "failed to parse yaml: {}", e),
+            Error::Json(e) => write!(f, "failed to parse json: {}", e),
+            Error::IndexOutOfRange { path, len } => write!(
+                f,
+                "array index out of range at `{}`, the array has length {}",
+                path, len
+            ),
+            Error::Input(e) => write!(f, "{}", e),
+            Error::CannotCreate {
+                child,
+                node_type,
+                path,
+            } => write!(
+                f,
+                "cannot create {} under {} at `{}`",
+                child, node_type, path
+            ),
+            Error::IndexTooLarge { index, max, path } => write!(
+                f,
+                "cannot create index `{}` at `{}`, arrays are only grown up to index {}",
+                index, path, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error {
+        Error::Parse(e)
+    }
+}
+
+impl From<EmitError> for Error {
+    fn from(e: EmitError) -> Error {
+        Error::Emit(e.to_string())
+    }
+}
+
+impl From<ScanError> for Error {
+    fn from(e: ScanError) -> Error {
+        Error::Scan(e)
+    }
+}
@@ -0,0 +1,130 @@
+use crate::error::Error;
+use log::debug;
+use std::fs;
+use std::path::Path;
+
+/// Extensions of the files searched when walking a directory.
+const SEARCHED_EXTENSIONS: [&str; 3] = ["yaml", "yml", "json"];
+
+/// Expands the inputs given on the command line into the files to search, in order. `-` and
+/// files are kept as they are, directories are walked recursively for yaml and json files, and
+/// glob patterns like `charts/**/*.yaml` are expanded. Files found in a directory or by a pattern
+/// are sorted by path.
+pub fn expand_inputs(inputs: &[&str]) -> Result<Vec<String>, Error> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if *input == "-" || path.is_file() {
+            files.push(input.to_string());
+        } else if path.is_dir() {
+            walk_dir(path, &mut files)?;
+        } else if input.contains(&['*', '?', '['][..]) {
+            expand_glob(input, &mut files)?;
+        } else {
+            // missing files fail once they are read, like any other unreadable file
+            files.push(input.to_string());
+        }
+    }
+    debug!("expanded inputs to files: {:?}", files);
+    Ok(files)
+}
+
+fn expand_glob(pattern: &str, files: &mut Vec<String>) -> Result<(), Error> {
+    let paths = glob::glob(pattern)
+        .map_err(|e| Error::Input(format!("invalid pattern `{}`: {}", pattern, e)))?;
+    let num_files = files.len();
+    for path in paths {
+        let path = path.map_err(|e| Error::Input(e.to_string()))?;
+        if path.is_dir() {
+            walk_dir(&path, files)?;
+        } else {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    if files.len() == num_files {
+        return Err(Error::Input(format!("no files match `{}`", pattern)));
+    }
+    Ok(())
+}
+
+fn walk_dir(dir: &Path, files: &mut Vec<String>) -> Result<(), Error> {
+    let read_err = |e: std::io::Error| {
+        Error::Input(format!(
+            "failed to read directory `{}`: {}",
+            dir.display(),
+            e
+        ))
+    };
+    let mut paths = fs::read_dir(dir)
+        .map_err(read_err)?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.path(), entry.file_type()?))
+        })
+        .collect::<Result<Vec<_>, std::io::Error>>()
+        .map_err(read_err)?;
+    paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, file_type) in paths {
+        if file_type.is_symlink() && path.is_dir() {
+            // like `grep -r`, linked directories are not followed, so links cannot form a cycle
+            debug!("skipping linked directory `{}`", path.display());
+        } else if file_type.is_dir() {
+            walk_dir(&path, files)?;
+        } else if is_searched_file(&path) {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+fn is_searched_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            SEARCHED_EXTENSIONS
+                .iter()
+                .any(|searched| ext.eq_ignore_ascii_case(searched))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_searched_file() {
+        assert!(is_searched_file(Path::new("a/values.yaml")));
+        assert!(is_searched_file(Path::new("a/values.YML")));
+        assert!(is_searched_file(Path::new("data.json")));
+        assert!(!is_searched_file(Path::new("README.md")));
+        assert!(!is_searched_file(Path::new("yaml")));
+    }
+
+    #[test]
+    fn test_expand_inputs_keeps_stdin_and_missing_files() {
+        assert_eq!(
+            expand_inputs(&["-", "does/not/exist.yml"]).unwrap(),
+            vec!["-", "does/not/exist.yml"]
+        );
+    }
+
+    #[test]
+    fn test_expand_inputs_pattern_without_matches_errs() {
+        assert!(expand_inputs(&["does/not/*.yml"]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expand_inputs_skips_linked_directories() {
+        let dir = std::env::temp_dir().join(format!("ry_inputs_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/x.yml"), "a: 1").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("sub/parent")).unwrap();
+        std::os::unix::fs::symlink("sub/x.yml", dir.join("y.yml")).unwrap();
+        let files = expand_inputs(&[dir.to_str().unwrap()]);
+        fs::remove_dir_all(&dir).unwrap();
+        let sub_file = dir.join("sub/x.yml").to_string_lossy().into_owned();
+        let linked_file = dir.join("y.yml").to_string_lossy().into_owned();
+        assert_eq!(files.unwrap(), vec![sub_file, linked_file]);
+    }
+}
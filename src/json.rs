@@ -0,0 +1,325 @@
+use crate::error::Error;
+use crate::marker::Marker;
+use std::fmt;
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+/// A syntax error in json input, located like `ScanError` is for yaml.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonError {
+    pub marker: Marker,
+    pub info: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {} column {}",
+            self.info,
+            self.marker.line,
+            self.marker.col + 1
+        )
+    }
+}
+
+/// Parses a json document into the same tree `YamlLoader` builds, so paths work the same on
+/// both. Integers that fit in an `i64` become `Yaml::Integer`, every other number `Yaml::Real`.
+pub fn load_json(source: &str) -> Result<Yaml, Error> {
+    let mut parser = JsonParser { source, pos: 0 };
+    parser.skip_whitespace();
+    let doc = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < source.len() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(doc)
+}
+
+struct JsonParser<'s> {
+    source: &'s str,
+    pos: usize,
+}
+
+impl<'s> JsonParser<'s> {
+    fn error(&self, info: &str) -> Error {
+        let line_offset = self.source[..self.pos].rfind('\n').map_or(0, |idx| idx + 1);
+        Error::Json(JsonError {
+            marker: Marker {
+                offset: self.pos,
+                line: self.source[..self.pos].matches('\n').count() + 1,
+                col: self.source[line_offset..self.pos].chars().count(),
+            },
+            info: info.to_string(),
+        })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), Error> {
+        if self.source[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", literal)))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Yaml, Error> {
+        match self.peek() {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => Ok(Yaml::String(self.parse_string()?)),
+            Some(b't') => self.expect("true").map(|_| Yaml::Boolean(true)),
+            Some(b'f') => self.expect("false").map(|_| Yaml::Boolean(false)),
+            Some(b'n') => self.expect("null").map(|_| Yaml::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.error("expected a json value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Yaml, Error> {
+        self.pos += 1;
+        let mut hash = Hash::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Yaml::Hash(hash));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+            hash.insert(Yaml::String(key), value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Yaml::Hash(hash));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Yaml, Error> {
+        self.pos += 1;
+        let mut array = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Yaml::Array(array));
+        }
+        loop {
+            self.skip_whitespace();
+            array.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Yaml::Array(array));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let run_len = self.source[self.pos..]
+                .find(|c: char| c == '"' || c == '\\' || c < ' ')
+                .ok_or_else(|| {
+                    self.pos = self.source.len();
+                    self.error("unterminated string")
+                })?;
+            s.push_str(&self.source[self.pos..self.pos + run_len]);
+            self.pos += run_len;
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    s.push(self.parse_escape()?);
+                }
+                _ => return Err(self.error("control character in string")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, Error> {
+        let escaped = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let unit = self.parse_hex4()?;
+                let code = if (0xd800..0xdc00).contains(&unit) {
+                    // a high surrogate has to be followed by an escaped low surrogate
+                    self.expect("\\u")?;
+                    let low = self.parse_hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error("invalid unicode surrogate pair"));
+                    }
+                    0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    unit
+                };
+                return std::char::from_u32(code)
+                    .ok_or_else(|| self.error("invalid unicode escape"));
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        self.pos += 1;
+        Ok(escaped)
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, Error> {
+        let hex = self
+            .source
+            .get(self.pos..self.pos + 4)
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("expected four hex digits"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(hex, 16).unwrap())
+    }
+
+    fn parse_number(&mut self) -> Result<Yaml, Error> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.skip_digits(),
+            _ => return Err(self.error("expected a digit")),
+        }
+        let mut is_integer = true;
+        if self.peek() == Some(b'.') {
+            is_integer = false;
+            self.pos += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit"));
+            }
+            self.skip_digits();
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            is_integer = false;
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit"));
+            }
+            self.skip_digits();
+        }
+        let number = &self.source[start..self.pos];
+        if is_integer {
+            if let Ok(i) = number.parse::<i64>() {
+                return Ok(Yaml::Integer(i));
+            }
+        }
+        Ok(Yaml::Real(number.to_string()))
+    }
+
+    fn skip_digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    fn error_at(source: &str) -> (usize, usize, String) {
+        match load_json(source) {
+            Err(Error::Json(e)) => (e.marker.line, e.marker.col, e.info),
+            other => panic!("expected a json error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_json_matches_yaml_loader() {
+        let source = r#"{"a": {"b": [1, -2.5e3, true, false, null, "x"]}, "c": {}, "d": []}"#;
+        assert_eq!(
+            load_json(source).unwrap(),
+            YamlLoader::load_from_str(source).unwrap().remove(0)
+        );
+    }
+
+    #[test]
+    fn test_load_json_numbers() {
+        assert_eq!(load_json("0").unwrap(), Yaml::Integer(0));
+        assert_eq!(load_json("-12").unwrap(), Yaml::Integer(-12));
+        assert_eq!(
+            load_json("99999999999999999999").unwrap(),
+            Yaml::Real("99999999999999999999".to_string())
+        );
+        assert_eq!(load_json("1.0").unwrap(), Yaml::Real("1.0".to_string()));
+        assert!(load_json("01").is_err());
+        assert!(load_json("1.").is_err());
+        assert!(load_json("-").is_err());
+    }
+
+    #[test]
+    fn test_load_json_strings() {
+        assert_eq!(
+            load_json(r#""tab\tquote\" slash\/ é 🦀""#).unwrap(),
+            Yaml::String("tab\tquote\" slash/ é 🦀".to_string())
+        );
+        assert!(load_json("\"a\nb\"").is_err());
+        assert_eq!(
+            load_json(r#""\u00e9\ud83e\udd80""#).unwrap(),
+            Yaml::String("é🦀".to_string())
+        );
+        assert!(load_json(r#""\ud83e""#).is_err());
+    }
+
+    #[test]
+    fn test_load_json_error_positions() {
+        assert_eq!(
+            error_at("{\n  \"a\": 1,\n  \"b\" 2\n}"),
+            (3, 6, "expected `:`".to_string())
+        );
+        assert_eq!(error_at("[1, 2"), (1, 5, "expected `,` or `]`".to_string()));
+        assert_eq!(
+            error_at("{\"é\": tru}"),
+            (1, 6, "expected `true`".to_string())
+        );
+        assert_eq!(
+            error_at("{} {}"),
+            (1, 3, "unexpected trailing characters".to_string())
+        );
+        assert_eq!(error_at(""), (1, 0, "unexpected end of input".to_string()));
+    }
+}
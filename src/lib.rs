@@ -1,12 +1,35 @@
 pub mod cli;
 pub mod convert;
+pub mod delete;
+pub mod error;
+pub mod inputs;
+pub mod json;
+pub mod lossless;
+pub mod marker;
+mod parallel;
 pub mod path;
+mod spans;
+pub mod stream;
 pub mod traverse;
+pub mod write;
 
 pub use cli::run_cli;
-pub use convert::{convert_length, convert_single_node, debug_print_doc_structure};
+pub use convert::{
+    convert_docs, convert_json, convert_length, convert_single_node, debug_print_doc_structure,
+};
+pub use delete::{delete, delete_with};
+pub use error::Error;
+pub use inputs::expand_inputs;
+pub use json::{load_json, JsonError};
+pub use lossless::render_lossless;
+pub use marker::{Marker, SourceMap};
 pub use path::{
     is_child_filter, is_child_filter_value_match, matches_pattern, parse_path, split_child_filter,
-    ArrayIndices, ParseError, SPLAT,
+    ArrayIndices, ChildFilter, FilterExpr, FilterOp, IndexSelector, ParseError, PathSegment, SPLAT,
+};
+pub use stream::{stream_count, stream_search, StreamEvent, StreamedNode};
+pub use traverse::{
+    traverse, traverse_mut, traverse_mut_with, traverse_strict, traverse_with, TraverseOptions,
+    VisitAction, VisitedNode,
 };
-pub use traverse::{traverse, VisitedNode};
+pub use write::{parse_value, write, write_with};
@@ -0,0 +1,599 @@
+use crate::convert::{convert_docs, get_node_structure};
+use crate::error::Error;
+use crate::spans::{column, line_start, load_spans, next_line_start, Span, SpanNode};
+use log::debug;
+use yaml_rust::scanner::TScalarStyle;
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+/// Renders `new_docs`, edited copies of the `old_docs` loaded from `source`, by patching `source`
+/// rather than re-emitting it. Only the bytes of changed nodes are rewritten, so comments, blank
+/// lines, quoting and flow style elsewhere in the file are preserved. Changes that cannot be
+/// patched in place re-emit the smallest enclosing map entry or array element, and a document is
+/// only re-emitted as a whole if its root cannot be patched. New lines end with the same line
+/// ending as the first line of `source`.
+pub fn render_lossless(
+    source: &str,
+    old_docs: &[Yaml],
+    new_docs: &[Yaml],
+) -> Result<String, Error> {
+    let newline = line_ending(source);
+    let doc_spans = match load_spans(source) {
+        Ok(doc_spans) if doc_spans.len() == old_docs.len() && old_docs.len() == new_docs.len() => {
+            doc_spans
+        }
+        _ => {
+            debug!("documents do not line up with the source, re-emitting all documents");
+            return Ok(with_line_ending(&convert_docs(new_docs)?, newline));
+        }
+    };
+
+    let mut patcher = Patcher {
+        source,
+        newline,
+        edits: Vec::new(),
+    };
+    for ((old, new), span_node) in old_docs.iter().zip(new_docs).zip(&doc_spans) {
+        if !patcher.patch_node(old, new, span_node)? {
+            debug!("unable to patch document root, re-emitting document");
+            let span = span_node.span();
+            let mut text = with_line_ending(&get_node_structure(new)?, newline);
+            if is_block_collection(new) && column(source, span.start) > 0 {
+                text.insert_str(0, newline);
+            }
+            if span.is_empty() {
+                text.push_str(newline);
+            }
+            patcher.replace(span.start, span.end, text);
+        }
+    }
+    Ok(patcher.apply())
+}
+
+struct Edit {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+struct Patcher<'s> {
+    source: &'s str,
+    newline: &'static str,
+    edits: Vec<Edit>,
+}
+
+impl<'s> Patcher<'s> {
+    fn replace(&mut self, start: usize, end: usize, text: String) {
+        debug!(
+            "replacing `{}` with `{}`",
+            &self.source[start..end],
+            text.escape_debug()
+        );
+        self.edits.push(Edit { start, end, text });
+    }
+
+    fn apply(mut self) -> String {
+        self.edits.sort_by_key(|edit| (edit.start, edit.end));
+        let mut out_str = String::with_capacity(self.source.len());
+        let mut pos = 0;
+        for edit in self.edits {
+            out_str.push_str(&self.source[pos..edit.start]);
+            out_str.push_str(&edit.text);
+            pos = edit.end;
+        }
+        out_str.push_str(&self.source[pos..]);
+        out_str
+    }
+
+    /// Records the edits turning `old` into `new`, returning false (with nothing recorded) if that
+    /// cannot be done without touching the text around `span_node`.
+    fn patch_node(&mut self, old: &Yaml, new: &Yaml, span_node: &SpanNode) -> Result<bool, Error> {
+        if old == new {
+            return Ok(true);
+        }
+        let checkpoint = self.edits.len();
+        let patched = match (old, new, span_node) {
+            (_, _, SpanNode::Scalar { span, style }) => {
+                if span.is_empty() || is_block_collection(new) {
+                    false
+                } else {
+                    self.replace(span.start, span.end, emit_scalar(new, *style)?);
+                    true
+                }
+            }
+            (
+                _,
+                _,
+                SpanNode::Map {
+                    span, flow: true, ..
+                },
+            )
+            | (
+                _,
+                _,
+                SpanNode::Seq {
+                    span, flow: true, ..
+                },
+            ) => {
+                if !self.patch_flow(old, new, span_node)? {
+                    self.edits.truncate(checkpoint);
+                    self.replace(span.start, span.end, emit_flow(new)?);
+                }
+                true
+            }
+            (Yaml::Hash(old_hash), Yaml::Hash(new_hash), SpanNode::Map { span, entries, .. }) => {
+                self.patch_block_map(old_hash, new_hash, entries, column(self.source, span.start))?
+            }
+            (Yaml::Array(old_array), Yaml::Array(new_array), SpanNode::Seq { span, items, .. }) => {
+                self.patch_block_seq(old_array, new_array, items, column(self.source, span.start))?
+            }
+            _ => false,
+        };
+        if !patched {
+            self.edits.truncate(checkpoint);
+        }
+        Ok(patched)
+    }
+
+    fn patch_block_map(
+        &mut self,
+        old_hash: &Hash,
+        new_hash: &Hash,
+        entries: &[(SpanNode, SpanNode)],
+        indent: usize,
+    ) -> Result<bool, Error> {
+        // duplicate keys mean the entries no longer line up with the loaded hash
+        if entries.len() != old_hash.len() || new_hash.is_empty() {
+            return Ok(false);
+        }
+        let mut new_entries = new_hash.iter();
+        let mut insert_at = 0;
+        for ((old_k, old_v), (key_node, value_node)) in old_hash.iter().zip(entries) {
+            let key_start = key_node.span().start;
+            let content_end = if value_node.span().is_empty() {
+                key_node.span().end
+            } else {
+                value_node.span().end
+            };
+            insert_at = next_line_start(self.source, content_end);
+            if !new_hash.contains_key(old_k) {
+                if !self.owns_line(key_start) {
+                    return Ok(false);
+                }
+                self.replace(line_start(self.source, key_start), insert_at, String::new());
+                continue;
+            }
+            let new_v = match new_entries.next() {
+                Some((new_k, new_v)) if new_k == old_k => new_v,
+                // entries were reordered, or inserted between existing ones
+                _ => return Ok(false),
+            };
+            if !self.patch_node(old_v, new_v, value_node)? {
+                if !self.owns_line(key_start) {
+                    return Ok(false);
+                }
+                let mut entry = Hash::new();
+                entry.insert(old_k.clone(), new_v.clone());
+                let text = emit_block(&Yaml::Hash(entry), indent, self.newline)?;
+                self.replace(line_start(self.source, key_start), insert_at, text);
+            }
+        }
+
+        let mut added = Hash::new();
+        for (k, v) in new_entries {
+            if old_hash.contains_key(k) {
+                return Ok(false);
+            }
+            added.insert(k.clone(), v.clone());
+        }
+        if !added.is_empty() {
+            let text = emit_block(&Yaml::Hash(added), indent, self.newline)?;
+            self.insert_lines(insert_at, text);
+        }
+        Ok(true)
+    }
+
+    fn patch_block_seq(
+        &mut self,
+        old_array: &[Yaml],
+        new_array: &[Yaml],
+        items: &[SpanNode],
+        indent: usize,
+    ) -> Result<bool, Error> {
+        if items.len() != old_array.len() || new_array.is_empty() {
+            return Ok(false);
+        }
+        if new_array.len() < old_array.len() {
+            // only removals: keep the items that line up with the new array, drop the rest
+            let mut new_idx = 0;
+            for (old_item, item_node) in old_array.iter().zip(items) {
+                if new_idx < new_array.len() && *old_item == new_array[new_idx] {
+                    new_idx += 1;
+                    continue;
+                }
+                match self.item_lines(item_node) {
+                    Some((start, end)) => self.replace(start, end, String::new()),
+                    None => return Ok(false),
+                }
+            }
+            return Ok(new_idx == new_array.len());
+        }
+
+        let mut insert_at = 0;
+        for ((old_item, new_item), item_node) in old_array.iter().zip(new_array).zip(items) {
+            let item_lines = self.item_lines(item_node);
+            if let Some((_, end)) = item_lines {
+                insert_at = end;
+            }
+            if !self.patch_node(old_item, new_item, item_node)? {
+                match item_lines {
+                    Some((start, end)) => {
+                        let text =
+                            emit_block(&Yaml::Array(vec![new_item.clone()]), indent, self.newline)?;
+                        self.replace(start, end, text);
+                    }
+                    None => return Ok(false),
+                }
+            }
+        }
+        if new_array.len() > old_array.len() {
+            if insert_at == 0 {
+                return Ok(false);
+            }
+            let added = new_array[old_array.len()..].to_vec();
+            let text = emit_block(&Yaml::Array(added), indent, self.newline)?;
+            self.insert_lines(insert_at, text);
+        }
+        Ok(true)
+    }
+
+    /// Patches the entries of a flow map or the items of a flow sequence one by one, so that
+    /// removing or appending one only touches it and its separator. Returns false if `new` is
+    /// not `old` with entries changed, removed or appended, or if it leaves the collection empty.
+    fn patch_flow(&mut self, old: &Yaml, new: &Yaml, span_node: &SpanNode) -> Result<bool, Error> {
+        let mut kept = Vec::new();
+        let mut added = Vec::new();
+        let spans: Vec<Span> = match (old, new, span_node) {
+            (Yaml::Hash(old_hash), Yaml::Hash(new_hash), SpanNode::Map { entries, .. }) => {
+                if entries.len() != old_hash.len() || new_hash.is_empty() {
+                    return Ok(false);
+                }
+                let mut new_entries = new_hash.iter();
+                for ((old_k, old_v), (_, value_node)) in old_hash.iter().zip(entries) {
+                    if !new_hash.contains_key(old_k) {
+                        kept.push(false);
+                        continue;
+                    }
+                    match new_entries.next() {
+                        Some((new_k, new_v)) if new_k == old_k => {
+                            if !self.patch_flow_child(old_v, new_v, value_node)? {
+                                return Ok(false);
+                            }
+                        }
+                        _ => return Ok(false),
+                    }
+                    kept.push(true);
+                }
+                for (k, v) in new_entries {
+                    if old_hash.contains_key(k) {
+                        return Ok(false);
+                    }
+                    added.push(format!("{}: {}", emit_flow(k)?, emit_flow(v)?));
+                }
+                entries
+                    .iter()
+                    .map(|(key_node, value_node)| Span {
+                        start: key_node.span().start,
+                        end: if value_node.span().is_empty() {
+                            key_node.span().end
+                        } else {
+                            value_node.span().end
+                        },
+                    })
+                    .collect()
+            }
+            (Yaml::Array(old_array), Yaml::Array(new_array), SpanNode::Seq { items, .. }) => {
+                if items.len() != old_array.len() || new_array.is_empty() {
+                    return Ok(false);
+                }
+                if new_array.len() < old_array.len() {
+                    // only removals: keep the items that line up with the new array
+                    let mut new_idx = 0;
+                    for old_item in old_array {
+                        let is_kept = new_idx < new_array.len() && *old_item == new_array[new_idx];
+                        if is_kept {
+                            new_idx += 1;
+                        }
+                        kept.push(is_kept);
+                    }
+                    if new_idx != new_array.len() {
+                        return Ok(false);
+                    }
+                } else {
+                    for ((old_item, new_item), item_node) in
+                        old_array.iter().zip(new_array).zip(items)
+                    {
+                        if !self.patch_flow_child(old_item, new_item, item_node)? {
+                            return Ok(false);
+                        }
+                        kept.push(true);
+                    }
+                    for item in &new_array[old_array.len()..] {
+                        added.push(emit_flow(item)?);
+                    }
+                }
+                items.iter().map(SpanNode::span).collect()
+            }
+            _ => return Ok(false),
+        };
+
+        let last_kept = match kept.iter().rposition(|is_kept| *is_kept) {
+            Some(last_kept) => last_kept,
+            None => {
+                // nothing is kept, so the new children take the place of all the old ones
+                return match (spans.first(), spans.last()) {
+                    (Some(first), Some(last)) => {
+                        self.replace(first.start, last.end, added.join(", "));
+                        Ok(true)
+                    }
+                    _ => Ok(false),
+                };
+            }
+        };
+        let mut idx = 0;
+        while idx < spans.len() {
+            if kept[idx] {
+                idx += 1;
+                continue;
+            }
+            let run_start = idx;
+            while idx < spans.len() && !kept[idx] {
+                idx += 1;
+            }
+            if idx < spans.len() {
+                // drop the run up to the next kept child, taking the separators after it
+                self.replace(spans[run_start].start, spans[idx].start, String::new());
+            } else {
+                // a trailing run takes the separator after the last kept child instead
+                self.replace(spans[run_start - 1].end, spans[idx - 1].end, String::new());
+            }
+        }
+        if !added.is_empty() {
+            let pos = spans[last_kept].end;
+            self.replace(pos, pos, format!(", {}", added.join(", ")));
+        }
+        Ok(true)
+    }
+
+    /// Patches a child of a flow collection, re-emitting it in flow style if it cannot be patched.
+    fn patch_flow_child(&mut self, old: &Yaml, new: &Yaml, node: &SpanNode) -> Result<bool, Error> {
+        if self.patch_node(old, new, node)? {
+            return Ok(true);
+        }
+        let span = node.span();
+        if span.is_empty() {
+            return Ok(false);
+        }
+        self.replace(span.start, span.end, emit_flow(new)?);
+        Ok(true)
+    }
+
+    /// Inserts whole lines at `pos`, which is the start of a line or the end of the source.
+    fn insert_lines(&mut self, pos: usize, mut text: String) {
+        if pos > 0 && !self.source[..pos].ends_with('\n') {
+            text.insert_str(0, self.newline);
+        }
+        self.replace(pos, pos, text);
+    }
+
+    /// Whether the node at `pos` is the first thing on its line, so its lines can be rewritten.
+    fn owns_line(&self, pos: usize) -> bool {
+        self.source[line_start(self.source, pos)..pos]
+            .chars()
+            .all(|c| c == ' ')
+    }
+
+    /// Returns the lines spanned by a block sequence item, from its `-` indicator to the end of
+    /// its content, as long as the indicator is the first thing on its line.
+    fn item_lines(&self, item_node: &SpanNode) -> Option<(usize, usize)> {
+        let span = item_node.span();
+        let before = self.source[..span.start].trim_end();
+        if !before.ends_with('-') {
+            return None;
+        }
+        let dash = before.len() - 1;
+        if !self.owns_line(dash) {
+            return None;
+        }
+        let content_end = if span.is_empty() { dash + 1 } else { span.end };
+        Some((
+            line_start(self.source, dash),
+            next_line_start(self.source, content_end),
+        ))
+    }
+}
+
+fn is_block_collection(node: &Yaml) -> bool {
+    match node {
+        Yaml::Hash(h) => !h.is_empty(),
+        Yaml::Array(a) => !a.is_empty(),
+        _ => false,
+    }
+}
+
+/// Emits a replacement scalar, keeping the quoting style of the scalar it replaces where it can.
+fn emit_scalar(node: &Yaml, old_style: TScalarStyle) -> Result<String, Error> {
+    if let Yaml::String(s) = node {
+        let is_single_line = !s.chars().any(char::is_control);
+        match old_style {
+            TScalarStyle::SingleQuoted if is_single_line => {
+                return Ok(format!("'{}'", s.replace('\'', "''")));
+            }
+            TScalarStyle::DoubleQuoted if is_single_line => {
+                return Ok(format!(
+                    "\"{}\"",
+                    s.replace('\\', "\\\\").replace('"', "\\\"")
+                ));
+            }
+            _ => {}
+        }
+    }
+    get_node_structure(node)
+}
+
+fn emit_flow(node: &Yaml) -> Result<String, Error> {
+    match node {
+        Yaml::Hash(h) => {
+            let mut entries = Vec::with_capacity(h.len());
+            for (k, v) in h {
+                entries.push(format!("{}: {}", emit_flow(k)?, emit_flow(v)?));
+            }
+            Ok(format!("{{{}}}", entries.join(", ")))
+        }
+        Yaml::Array(a) => {
+            let mut items = Vec::with_capacity(a.len());
+            for item in a {
+                items.push(emit_flow(item)?);
+            }
+            Ok(format!("[{}]", items.join(", ")))
+        }
+        _ => get_node_structure(node),
+    }
+}
+
+/// Emits `node` in block style as whole lines indented by `indent` spaces.
+fn emit_block(node: &Yaml, indent: usize, newline: &str) -> Result<String, Error> {
+    let mut out_str = String::new();
+    for line in get_node_structure(node)?.lines() {
+        out_str.push_str(&" ".repeat(indent));
+        out_str.push_str(line);
+        out_str.push_str(newline);
+    }
+    Ok(out_str)
+}
+
+/// The line ending of the first line of `source`, `\n` if it has a single line.
+fn line_ending(source: &str) -> &'static str {
+    match source.find('\n') {
+        Some(idx) if source[..idx].ends_with('\r') => "\r\n",
+        _ => "\n",
+    }
+}
+
+fn with_line_ending(text: &str, newline: &str) -> String {
+    if newline == "\n" {
+        return text.to_string();
+    }
+    text.replace('\n', newline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delete::delete;
+    use crate::path::parse_path;
+    use crate::write::{parse_value, write};
+    use yaml_rust::YamlLoader;
+
+    fn write_lossless(source: &str, path: &str, value: &str) -> String {
+        let old_docs = YamlLoader::load_from_str(source).unwrap();
+        let mut new_docs = old_docs.clone();
+        for doc in new_docs.iter_mut() {
+            write(doc, &parse_path(path).unwrap(), &parse_value(value)).unwrap();
+        }
+        render_lossless(source, &old_docs, &new_docs).unwrap()
+    }
+
+    fn delete_lossless(source: &str, path: &str) -> String {
+        let old_docs = YamlLoader::load_from_str(source).unwrap();
+        let mut new_docs = old_docs.clone();
+        for doc in new_docs.iter_mut() {
+            delete(doc, &parse_path(path).unwrap()).unwrap();
+        }
+        render_lossless(source, &old_docs, &new_docs).unwrap()
+    }
+
+    #[test]
+    fn test_render_lossless_unchanged() {
+        let source = "# top\na:   1 # one\n\nb: [x,   y]\n";
+        assert_eq!(write_lossless(source, "a", "1"), source);
+    }
+
+    #[test]
+    fn test_render_lossless_scalar() {
+        assert_eq!(
+            write_lossless("# top\na:\n  b: 1 # one\n\nc: 2\n", "a.b", "3"),
+            "# top\na:\n  b: 3 # one\n\nc: 2\n"
+        );
+        assert_eq!(
+            write_lossless("a: 'x'\nb: \"y\"\n", "*", "it's"),
+            "a: 'it''s'\nb: \"it's\"\n"
+        );
+        assert_eq!(
+            write_lossless("a: |\n  text\nb: 1\n", "a", "other"),
+            "a: other\nb: 1\n"
+        );
+    }
+
+    #[test]
+    fn test_render_lossless_flow() {
+        assert_eq!(
+            write_lossless("a: [1, 2] # nums\n", "a[2]", "3"),
+            "a: [1, 2, 3] # nums\n"
+        );
+        assert_eq!(
+            write_lossless("a: {b: [1,  2], c: x}\n", "a.b[0]", "{d: e}"),
+            "a: {b: [{d: e},  2], c: x}\n"
+        );
+    }
+
+    #[test]
+    fn test_render_lossless_add_entries() {
+        assert_eq!(
+            write_lossless("a:\n  b: 1 # one\n# trailing\n", "a.c.d", "2"),
+            "a:\n  b: 1 # one\n  c:\n    d: 2\n# trailing\n"
+        );
+        assert_eq!(write_lossless("a: 1", "b", "2"), "a: 1\nb: 2\n");
+        assert_eq!(
+            write_lossless("a:\n  - x # first\n", "a[1]", "y"),
+            "a:\n  - x # first\n  - y\n"
+        );
+        assert_eq!(write_lossless("a:\n", "a.b", "1"), "a:\n  b: 1\n");
+    }
+
+    #[test]
+    fn test_render_lossless_type_change() {
+        assert_eq!(
+            write_lossless("# keep\na: 1 # gone\nb: 2\n", "a", "{c: d}"),
+            "# keep\na:\n  c: d\nb: 2\n"
+        );
+    }
+
+    #[test]
+    fn test_render_lossless_delete() {
+        assert_eq!(
+            delete_lossless("# top\na: 1 # one\nb:\n  c: 2\n\nd: 3\n", "b"),
+            "# top\na: 1 # one\n\nd: 3\n"
+        );
+        assert_eq!(
+            delete_lossless("- a # x\n- b\n- a\n- c # z\n", "(.==a)"),
+            "- b\n- c # z\n"
+        );
+    }
+
+    #[test]
+    fn test_render_lossless_delete_last_entry_reemits() {
+        assert_eq!(
+            delete_lossless("a:\n  b: 1\nc: 2\n", "a.b"),
+            "a: {}\nc: 2\n"
+        );
+    }
+
+    #[test]
+    fn test_render_lossless_multi_doc() {
+        assert_eq!(
+            write_lossless("a: 1 # one\n---\n# two\na: 2\n", "a", "3"),
+            "a: 3 # one\n---\n# two\na: 3\n"
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use crate::error::Error;
+use crate::spans::{line_start, load_spans, SpanNode};
+use crate::traverse::VisitedNode;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use yaml_rust::Yaml;
+
+/// Where a node starts in the source it was loaded from. Like `yaml_rust::scanner::Marker`, lines
+/// start at 1 and columns are counted in chars from 0, but `offset` is in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Marker {
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Marker {
+    /// Marker of byte `pos` in `text`, where `text` starts at `start` and `start` begins a line.
+    fn within(text: &str, pos: usize, start: Marker) -> Marker {
+        let line_offset = line_start(text, pos);
+        Marker {
+            offset: start.offset + pos,
+            line: start.line + text[..pos].matches('\n').count(),
+            col: text[line_offset..pos].chars().count(),
+        }
+    }
+}
+
+/// Start markers of a loaded node and its children, in the shape of the node. Aliases only have
+/// the marker of the alias itself, which is used for the whole copy of the anchored node.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MarkTree {
+    Node(Marker),
+    Map(Marker, Vec<(MarkTree, MarkTree)>),
+    Seq(Marker, Vec<MarkTree>),
+}
+
+impl MarkTree {
+    fn from_spans(span_node: &SpanNode, lines: &LineIndex) -> MarkTree {
+        let marker = lines.marker(span_node.span().start);
+        match span_node {
+            SpanNode::Map { entries, .. } => MarkTree::Map(
+                marker,
+                entries
+                    .iter()
+                    .map(|(k, v)| {
+                        (
+                            MarkTree::from_spans(k, lines),
+                            MarkTree::from_spans(v, lines),
+                        )
+                    })
+                    .collect(),
+            ),
+            SpanNode::Seq { items, .. } => MarkTree::Seq(
+                marker,
+                items
+                    .iter()
+                    .map(|item| MarkTree::from_spans(item, lines))
+                    .collect(),
+            ),
+            SpanNode::Scalar { .. } | SpanNode::Alias { .. } => MarkTree::Node(marker),
+        }
+    }
+
+    fn marker(&self) -> Marker {
+        match self {
+            MarkTree::Node(marker) | MarkTree::Map(marker, _) | MarkTree::Seq(marker, _) => *marker,
+        }
+    }
+}
+
+/// Byte offsets of the start of every line in a source.
+struct LineIndex<'s> {
+    source: &'s str,
+    starts: Vec<usize>,
+}
+
+impl<'s> LineIndex<'s> {
+    fn new(source: &'s str) -> LineIndex<'s> {
+        let mut starts = vec![0];
+        starts.extend(source.match_indices('\n').map(|(idx, _)| idx + 1));
+        LineIndex { source, starts }
+    }
+
+    fn marker(&self, offset: usize) -> Marker {
+        let line_idx = match self.starts.binary_search(&offset) {
+            Ok(idx) => idx,
+            Err(idx) => idx - 1,
+        };
+        let line_offset = self.starts[line_idx];
+        Marker {
+            offset,
+            line: line_idx + 1,
+            col: self.source[line_offset..offset].chars().count(),
+        }
+    }
+}
+
+/// Start markers of the nodes of loaded documents, looked up by node. Nodes are told apart by
+/// their address, so the documents are borrowed for as long as the map is around.
+#[derive(Debug)]
+pub struct SourceMap<'a> {
+    markers: HashMap<usize, Marker>,
+    docs: PhantomData<&'a Yaml>,
+}
+
+impl<'a> SourceMap<'a> {
+    /// Builds the source map of `docs`, which must have been loaded from `source`.
+    pub fn new(source: &str, docs: &'a [Yaml]) -> Result<SourceMap<'a>, Error> {
+        let lines = LineIndex::new(source);
+        let mut source_map = SourceMap {
+            markers: HashMap::new(),
+            docs: PhantomData,
+        };
+        for (doc, span_node) in docs.iter().zip(load_spans(source)?.iter()) {
+            source_map.insert(doc, &MarkTree::from_spans(span_node, &lines));
+        }
+        Ok(source_map)
+    }
+
+    pub(crate) fn from_tree(node: &'a Yaml, tree: &MarkTree) -> SourceMap<'a> {
+        let mut source_map = SourceMap {
+            markers: HashMap::new(),
+            docs: PhantomData,
+        };
+        source_map.insert(node, tree);
+        source_map
+    }
+
+    fn insert(&mut self, node: &Yaml, tree: &MarkTree) {
+        self.markers
+            .insert(node as *const Yaml as usize, tree.marker());
+        match (node, tree) {
+            (Yaml::Hash(hash), MarkTree::Map(_, entries)) => {
+                for ((k, v), (k_tree, v_tree)) in hash.iter().zip(entries) {
+                    self.insert(k, k_tree);
+                    self.insert(v, v_tree);
+                }
+            }
+            (Yaml::Array(array), MarkTree::Seq(_, items)) => {
+                for (item, item_tree) in array.iter().zip(items) {
+                    self.insert(item, item_tree);
+                }
+            }
+            (Yaml::Hash(hash), MarkTree::Node(_)) => {
+                for (k, v) in hash {
+                    self.insert(k, tree);
+                    self.insert(v, tree);
+                }
+            }
+            (Yaml::Array(array), MarkTree::Node(_)) => {
+                for item in array {
+                    self.insert(item, tree);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the marker of `node`, if it is one of the mapped nodes.
+    pub fn marker(&self, node: &Yaml) -> Option<Marker> {
+        self.markers.get(&(node as *const Yaml as usize)).copied()
+    }
+
+    /// Sets the marker of every visited node.
+    pub fn mark(&self, visited: &mut [VisitedNode<'a>]) {
+        for visited_node in visited {
+            visited_node.marker = self.marker(visited_node.yml);
+        }
+    }
+}
+
+/// The part of a char stream that has been read but not released yet, so the markers parser
+/// events are reported at can be turned into `Marker`s without keeping the whole source around.
+#[derive(Debug)]
+pub(crate) struct SourceWindow {
+    pub text: String,
+    // marker and char index of the start of `text`, which is always the start of a line
+    start: Marker,
+    start_char: usize,
+}
+
+impl SourceWindow {
+    pub fn new() -> SourceWindow {
+        SourceWindow {
+            text: String::new(),
+            start: Marker {
+                offset: 0,
+                line: 1,
+                col: 0,
+            },
+            start_char: 0,
+        }
+    }
+
+    /// Byte offset in `text` of the char at `char_idx` in the stream.
+    pub fn offset_of(&self, char_idx: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(char_idx.saturating_sub(self.start_char))
+            .map_or(self.text.len(), |(idx, _)| idx)
+    }
+
+    /// Marker of byte `pos` in `text`.
+    pub fn marker(&self, pos: usize) -> Marker {
+        Marker::within(&self.text, pos, self.start)
+    }
+
+    /// Drops the lines before the one containing the char at `char_idx`.
+    pub fn release(&mut self, char_idx: usize) {
+        let cut = line_start(&self.text, self.offset_of(char_idx));
+        if cut == 0 {
+            return;
+        }
+        self.start = Marker::within(&self.text, cut, self.start);
+        self.start_char += self.text[..cut].chars().count();
+        self.text.drain(..cut);
+    }
+}
+
+/// Passes chars through while adding them to a `SourceWindow`.
+pub(crate) struct WindowedChars<I> {
+    pub chars: I,
+    pub window: Rc<RefCell<SourceWindow>>,
+}
+
+impl<I: Iterator<Item = char>> Iterator for WindowedChars<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.window.borrow_mut().text.push(c);
+        Some(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::parse_path;
+    use crate::traverse::traverse;
+    use yaml_rust::YamlLoader;
+
+    fn visited_markers(source: &str, path: &str) -> Vec<(String, usize, usize)> {
+        let docs = YamlLoader::load_from_str(source).unwrap();
+        let source_map = SourceMap::new(source, &docs).unwrap();
+        let mut visited = Vec::new();
+        for doc in &docs {
+            traverse(doc, &parse_path(path).unwrap(), &mut visited).unwrap();
+        }
+        source_map.mark(&mut visited);
+        visited
+            .into_iter()
+            .map(|v| {
+                let marker = v.marker.unwrap();
+                (v.path, marker.line, marker.col)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_source_map_markers() {
+        let source = "a:\n  b: |\n    text\n  c:\n  d:\n    - x\n    - {e: é, f: 1}\n";
+        assert_eq!(
+            visited_markers(source, "a.*"),
+            vec![
+                ("a.b".to_string(), 2, 5),
+                ("a.c".to_string(), 4, 4),
+                ("a.d".to_string(), 6, 4),
+            ]
+        );
+        assert_eq!(
+            visited_markers(source, "a.d[1].*"),
+            vec![
+                ("a.d[1].e".to_string(), 7, 10),
+                ("a.d[1].f".to_string(), 7, 16)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_map_multi_doc_and_aliases() {
+        let source = "d: 0\n---\na: &x\n  b: 1\nc: *x\n";
+        assert_eq!(
+            visited_markers(source, "**"),
+            vec![
+                ("d".to_string(), 1, 3),
+                ("a.b".to_string(), 4, 5),
+                ("c.b".to_string(), 5, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_window_release() {
+        let mut window = SourceWindow::new();
+        window.text.push_str("ab\ncé\nd");
+        window.release(6);
+        assert_eq!(window.text, "d");
+        assert_eq!(window.offset_of(6), 0);
+        assert_eq!(
+            window.marker(0),
+            Marker {
+                offset: 7,
+                line: 3,
+                col: 0
+            }
+        );
+    }
+}
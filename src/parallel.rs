@@ -0,0 +1,160 @@
+use crate::spans::split_documents;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use yaml_rust::{ScanError, Yaml, YamlLoader};
+
+/// Runs `work` for each index in `0..len` on up to `jobs` threads, and hands the results to `emit`
+/// on the calling thread in index order, each as soon as all of those before it are done. Once
+/// `emit` returns false no more work is started and no more results are emitted.
+pub(crate) fn for_each_ordered<R, W, E>(len: usize, jobs: usize, work: W, mut emit: E)
+where
+    R: Send,
+    W: Fn(usize) -> R + Sync,
+    E: FnMut(R) -> bool,
+{
+    let next_idx = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(len) {
+            let sender = sender.clone();
+            let (next_idx, work) = (&next_idx, &work);
+            scope.spawn(move || loop {
+                let idx = next_idx.fetch_add(1, Ordering::Relaxed);
+                if idx >= len || sender.send((idx, work(idx))).is_err() {
+                    break;
+                }
+            });
+        }
+        // the receiver stops once every worker is done with its sender
+        drop(sender);
+
+        let mut done = BTreeMap::new();
+        let mut next_emit = 0;
+        for (idx, result) in receiver {
+            done.insert(idx, result);
+            while let Some(result) = done.remove(&next_emit) {
+                if !emit(result) {
+                    // workers stop at their next index, or when they fail to send a result
+                    next_idx.store(len, Ordering::Relaxed);
+                    return;
+                }
+                next_emit += 1;
+            }
+        }
+    });
+}
+
+/// Loads the documents of a yaml stream on up to `jobs` threads, each of them loading the
+/// documents between two `---` lines.
+pub(crate) fn load_docs_ordered(source: &str, jobs: usize) -> Result<Vec<Yaml>, ScanError> {
+    let chunks = if jobs > 1 {
+        split_documents(source)
+    } else {
+        vec![source]
+    };
+    if chunks.len() < 2 {
+        return YamlLoader::load_from_str(source);
+    }
+    let mut docs = Vec::new();
+    let mut failed = false;
+    for_each_ordered(
+        chunks.len(),
+        jobs,
+        |idx| YamlLoader::load_from_str(chunks[idx]),
+        |loaded| match loaded {
+            Ok(chunk_docs) => {
+                docs.extend(chunk_docs);
+                true
+            }
+            Err(_) => {
+                failed = true;
+                false
+            }
+        },
+    );
+    if failed {
+        // the error's position is within its chunk, loading the whole stream reports it in the file
+        return YamlLoader::load_from_str(source);
+    }
+    Ok(docs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_for_each_ordered_emits_in_index_order() {
+        let mut emitted = Vec::new();
+        for_each_ordered(
+            20,
+            4,
+            |idx| {
+                // later indices finish first
+                thread::sleep(Duration::from_millis(20 - idx as u64));
+                idx * 2
+            },
+            |result| {
+                emitted.push(result);
+                true
+            },
+        );
+        assert_eq!(emitted, (0..20).map(|idx| idx * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_for_each_ordered_stops_when_emit_returns_false() {
+        let started = AtomicUsize::new(0);
+        let mut emitted = Vec::new();
+        for_each_ordered(
+            1000,
+            2,
+            |idx| {
+                started.fetch_add(1, Ordering::Relaxed);
+                thread::sleep(Duration::from_millis(1));
+                idx
+            },
+            |result| {
+                emitted.push(result);
+                result < 3
+            },
+        );
+        assert_eq!(emitted, vec![0, 1, 2, 3]);
+        assert!(started.load(Ordering::Relaxed) < 1000);
+    }
+
+    #[test]
+    fn test_for_each_ordered_without_work() {
+        let mut emitted = Vec::<usize>::new();
+        for_each_ordered(
+            0,
+            4,
+            |idx| idx,
+            |result| {
+                emitted.push(result);
+                true
+            },
+        );
+        assert!(emitted.is_empty());
+    }
+
+    #[test]
+    fn test_load_docs_ordered_matches_loading_on_one_thread() {
+        let source = "# top\na: 1\n---\n- b\n--- |\n  c\n...\n%YAML 1.2\n---\nd: {e: f}\n";
+        let docs = load_docs_ordered(source, 3).unwrap();
+        assert_eq!(docs.len(), 4);
+        assert_eq!(docs, YamlLoader::load_from_str(source).unwrap());
+    }
+
+    #[test]
+    fn test_load_docs_ordered_reports_errors_in_the_file() {
+        let source = "a: 1\n---\nb: 2\n---\nc: [\n";
+        assert_eq!(
+            load_docs_ordered(source, 2).unwrap_err(),
+            YamlLoader::load_from_str(source).unwrap_err()
+        );
+    }
+}
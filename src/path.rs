@@ -1,15 +1,310 @@
+use crate::error::Error;
 use crate::traverse::{traverse, VisitedNode};
 use log::debug;
+use regex::Regex;
+use std::cmp::Ordering;
 use std::fmt;
 use yaml_rust::Yaml;
 
-pub const SPLAT: &'static str = "**";
-pub const CHILD_FILTER_DELIM: &'static str = "==";
+pub const SPLAT: &str = "**";
+pub const CHILD_FILTER_DELIM: &str = "==";
+pub const REGEX_FILTER_DELIM: &str = "=~";
+/// Starts a key regex such as `~/^item_\d+$/`, so plain keys like `/api/` stay keys.
+pub const KEY_REGEX_PREFIX: &str = "~/";
+pub const HAS_PREDICATE: &str = "has";
+pub const TYPE_PREDICATE: &str = "type";
 
-#[derive(Debug, PartialEq)]
+/// Names `type(path)` compares against, see `type_name`.
+pub const TYPE_NAMES: [&str; 7] = ["map", "seq", "string", "int", "float", "bool", "null"];
+
+/// Operators a child filter can compare with, longest first so `<=` isn't read as `<`.
+const FILTER_OPS: [&str; 7] = [
+    CHILD_FILTER_DELIM,
+    REGEX_FILTER_DELIM,
+    "!=",
+    "<=",
+    ">=",
+    "<",
+    ">",
+];
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ArrayIndices {
     Star,
     Indices(Vec<usize>),
+    /// indices that depend on the array's length, e.g. `[-1]` or `[1:3]`, resolved into
+    /// `Indices` once the array is reached
+    Relative(Vec<IndexSelector>),
+}
+
+/// One comma separated element of an array indexing operation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexSelector {
+    /// a single index, counted from the end if negative
+    At(i64),
+    /// a Python-style `start:end:step` slice
+    Slice {
+        start: Option<i64>,
+        end: Option<i64>,
+        step: i64,
+    },
+}
+
+impl ArrayIndices {
+    /// The absolute indices selected in an array of length `len`, in order, and the single
+    /// indices that are out of range for it, as they were written.
+    pub fn resolve(&self, len: usize) -> (Vec<usize>, Vec<i64>) {
+        match self {
+            ArrayIndices::Star => ((0..len).collect(), vec![]),
+            ArrayIndices::Indices(indices) => {
+                let (in_range, out_of_range) = indices.iter().partition(|i| **i < len);
+                (
+                    in_range,
+                    out_of_range.into_iter().map(|i| i as i64).collect(),
+                )
+            }
+            ArrayIndices::Relative(selectors) => {
+                let mut indices = Vec::new();
+                let mut out_of_range = Vec::new();
+                for selector in selectors {
+                    match selector.resolve(len) {
+                        Ok(selected) => indices.extend(selected),
+                        Err(i) => out_of_range.push(i),
+                    }
+                }
+                (indices, out_of_range)
+            }
+        }
+    }
+
+    /// Whether element `idx` may be selected in an array found to have at least `min_len`
+    /// elements so far. Only gives false for elements that no final length can select, which is
+    /// settled for good once `min_len` passes `idx + self.reach_from_end()`.
+    pub fn may_select(&self, idx: usize, min_len: usize) -> bool {
+        match self {
+            ArrayIndices::Star => true,
+            ArrayIndices::Indices(indices) => indices.contains(&idx),
+            ArrayIndices::Relative(selectors) => selectors
+                .iter()
+                .any(|selector| selector.may_select(idx, min_len)),
+        }
+    }
+
+    /// How many elements from the end of an array the negative indices and slice bounds reach.
+    pub fn reach_from_end(&self) -> usize {
+        match self {
+            ArrayIndices::Relative(selectors) => selectors
+                .iter()
+                .map(IndexSelector::reach_from_end)
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Whether element `idx` is selected in an array found to have at least `min_len` elements
+    /// so far, whatever its final length, with every element before it that gets selected being
+    /// selected first. Only ever true for a single slice with a positive step.
+    pub fn selects_in_order(&self, idx: usize, min_len: usize) -> bool {
+        match self {
+            ArrayIndices::Relative(selectors) => match selectors.as_slice() {
+                [selector] => selector.always_selects(idx, min_len),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl IndexSelector {
+    /// The absolute indices selected in an array of length `len`, in order. Slices are clamped
+    /// to the array like in Python, a single index out of range is returned as the error.
+    pub fn resolve(&self, len: usize) -> Result<Vec<usize>, i64> {
+        let len = len as i64;
+        let absolute = |i: i64| if i < 0 { i + len } else { i };
+        match *self {
+            IndexSelector::At(i) => {
+                let abs = absolute(i);
+                if abs < 0 || abs >= len {
+                    Err(i)
+                } else {
+                    Ok(vec![abs as usize])
+                }
+            }
+            IndexSelector::Slice { start, end, step } => {
+                // a negative step walks down from the end, stopping before `end` which may be -1
+                let (lowest, highest) = if step > 0 { (0, len) } else { (-1, len - 1) };
+                let clamp = |i: i64| absolute(i).max(lowest).min(highest);
+                let mut i = start.map_or(if step > 0 { 0 } else { len - 1 }, clamp);
+                let end = end.map_or(if step > 0 { len } else { -1 }, clamp);
+                let mut indices = Vec::new();
+                while (step > 0 && i < end) || (step < 0 && i > end) {
+                    indices.push(i as usize);
+                    match i.checked_add(step) {
+                        Some(next) => i = next,
+                        None => break,
+                    }
+                }
+                Ok(indices)
+            }
+        }
+    }
+
+    fn may_select(&self, idx: usize, min_len: usize) -> bool {
+        let (idx, min_len) = (idx as i128, min_len as i128);
+        match *self {
+            IndexSelector::At(i) if i >= 0 => idx == i as i128,
+            // the array would need a length of `idx - i`
+            IndexSelector::At(i) => idx - i as i128 >= min_len,
+            IndexSelector::Slice { start, end, step } if step > 0 => {
+                // a negative start only moves up as the array grows
+                let (lowest_start, is_aligned) = match start.map(i128::from) {
+                    None => (0, idx % step as i128 == 0),
+                    Some(start) if start >= 0 => (start, (idx - start) % step as i128 == 0),
+                    Some(start) => ((min_len + start).max(0), true),
+                };
+                idx >= lowest_start
+                    && is_aligned
+                    && end.map_or(true, |end| end < 0 || idx < end as i128)
+            }
+            IndexSelector::Slice { start, end, .. } => {
+                // walking down, a negative end only moves up as the array grows
+                let lowest_end = match end.map(i128::from) {
+                    None => -1,
+                    Some(end) if end >= 0 => end,
+                    Some(end) => (min_len + end).max(-1),
+                };
+                idx > lowest_end && start.map_or(true, |start| start < 0 || idx <= start as i128)
+            }
+        }
+    }
+
+    fn always_selects(&self, idx: usize, min_len: usize) -> bool {
+        let (idx, min_len) = (idx as i128, min_len as i128);
+        match *self {
+            IndexSelector::Slice { start, end, step } if step > 0 => {
+                let start = match start {
+                    None => 0,
+                    Some(start) if start >= 0 => start as i128,
+                    // a negative start moves past every element eventually
+                    Some(_) => return false,
+                };
+                let is_before_end = match end.map(i128::from) {
+                    None => true,
+                    Some(end) if end >= 0 => idx < end,
+                    Some(end) => idx < min_len + end,
+                };
+                idx >= start && (idx - start) % step as i128 == 0 && is_before_end
+            }
+            _ => false,
+        }
+    }
+
+    fn reach_from_end(&self) -> usize {
+        let reach = |i: i64| if i < 0 { i.unsigned_abs() as usize } else { 0 };
+        match *self {
+            IndexSelector::At(i) => reach(i),
+            IndexSelector::Slice { start, end, .. } => {
+                reach(start.unwrap_or(0)).max(reach(end.unwrap_or(0)))
+            }
+        }
+    }
+}
+
+/// The expression inside a child filter's parens: conditions combined with `&&`, `||`, `!` and
+/// grouping parens, where `&&` binds tighter than `||`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    Cond(ChildFilter),
+    /// `has(path)`, whether `path` leads to at least one node
+    Has(Vec<PathSegment>),
+    /// `type(path)==map`, comparing the type name of the single node `path` leads to
+    Type(ChildFilter),
+    Not(Box<FilterExpr>),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+}
+
+/// A single child filter condition such as `b.c==magic`: `path` is searched relative to each
+/// candidate node and the single value it finds is compared against `value` with `op`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChildFilter {
+    pub path: Vec<PathSegment>,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+/// How a child filter compares the value it finds against its own value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterOp {
+    /// `==`, matching the value as a string pattern
+    Eq,
+    /// `!=`, the negation of `==`
+    Ne,
+    /// `=~`, searching the value as a string for a regular expression
+    Match(PathRegex),
+    /// `<`, `<=`, `>` and `>=` compare integers and reals numerically, other values never match
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl FilterOp {
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            FilterOp::Lt | FilterOp::Le | FilterOp::Gt | FilterOp::Ge
+        )
+    }
+}
+
+/// A regular expression compiled while parsing a path. Two are equal if their sources are.
+#[derive(Debug, Clone)]
+pub struct PathRegex(pub Regex);
+
+impl PathRegex {
+    pub fn new(re: &str) -> Result<PathRegex, ParseError> {
+        Regex::new(re)
+            .map(PathRegex)
+            .map_err(|e| ParseError::new(&format!("invalid regular expression `{}`: {}", re, e)))
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        self.0.is_match(s)
+    }
+}
+
+impl PartialEq for PathRegex {
+    fn eq(&self, other: &PathRegex) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+/// A single, validated element of a path expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    /// a map key matched exactly, e.g. `a` or `"foo.bar"`
+    Key(String),
+    /// a map key glob pattern, e.g. `item*` or `*_name?`
+    Glob(String),
+    /// a map key regular expression between slashes after a `~` (`KEY_REGEX_PREFIX`), e.g.
+    /// `~/^item_\d+$/`
+    Regex(PathRegex),
+    /// alternative map keys or key patterns, e.g. `(b|c*)` or `{b,c*}`
+    Union(Vec<PathSegment>),
+    /// the deep splat `**`
+    Splat,
+    /// an array indexing operation, e.g. `[1]` or `[*]`
+    Index(ArrayIndices),
+    /// a child filter, e.g. `(b.c==magic)` or `(kind==Pod && !(name==web*))`
+    ChildFilter(FilterExpr),
+}
+
+impl PathSegment {
+    pub fn is_splat(&self) -> bool {
+        *self == PathSegment::Splat
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -36,7 +331,7 @@ enum PathElem {
     ArrayClose,
     ParenOpen,
     ParenClose,
-    EOW,
+    Eow,
 }
 
 fn char_is(c: char) -> PathElem {
@@ -52,31 +347,280 @@ fn char_is(c: char) -> PathElem {
 }
 
 fn next_special_char_is(s: &str) -> (PathElem, usize) {
-    for (idx, c) in s.chars().enumerate() {
+    for (idx, c) in s.char_indices() {
         let path_elem = char_is(c);
         if path_elem != PathElem::Char {
             return (path_elem, idx);
         }
     }
-    (PathElem::EOW, s.len())
+    (PathElem::Eow, s.len())
 }
 
 fn next_specific_special_char(s: &str, pe: PathElem) -> (bool, usize) {
-    for (idx, c) in s.chars().enumerate() {
+    for (idx, c) in s.char_indices() {
         if char_is(c) == pe {
             return (true, idx);
         }
     }
     match pe {
-        PathElem::EOW => (true, s.len()),
+        PathElem::Eow => (true, s.len()),
         _ => (false, 0),
     }
 }
 
-pub fn parse_path(path: &str) -> Result<Vec<String>, ParseError> {
-    let mut parsed_path: Vec<String> = vec![];
+/// Byte index of the paren closing the one `s` starts with, skipping nested pairs.
+fn matching_paren_close(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, c) in s.char_indices() {
+        match char_is(c) {
+            PathElem::ParenOpen => depth += 1,
+            PathElem::ParenClose => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parses a key regex such as `~/^item_\d+$/` at the start of `s`, returning it with the byte
+/// length it spans. `s` is only a regex if the closing slash ends the key, otherwise `None`.
+fn parse_key_regex(s: &str) -> Result<Option<(PathSegment, usize)>, ParseError> {
+    let pattern_start = KEY_REGEX_PREFIX.len();
+    if !s.starts_with(KEY_REGEX_PREFIX) {
+        return Ok(None);
+    }
+    let mut escaped = false;
+    for (idx, c) in s.char_indices().skip(pattern_start) {
+        match c {
+            '\\' => escaped = !escaped,
+            '/' if !escaped => {
+                let end = idx + 1;
+                if !s[end..].is_empty() && !s[end..].starts_with(&['.', '[', '('][..]) {
+                    return Ok(None);
+                }
+                let re = PathRegex::new(&s[pattern_start..idx].replace("\\/", "/"))?;
+                return Ok(Some((PathSegment::Regex(re), end)));
+            }
+            _ => escaped = false,
+        }
+    }
+    Ok(None)
+}
+
+fn parse_key(key: &str) -> PathSegment {
+    if key == SPLAT {
+        PathSegment::Splat
+    } else if let Some(union) = key
+        .strip_prefix('{')
+        .and_then(|key| key.strip_suffix('}'))
+        .and_then(|alternatives| parse_union(alternatives, ','))
+    {
+        union
+    } else if key.contains(&['*', '?', '\\'][..]) {
+        PathSegment::Glob(key.to_string())
+    } else {
+        PathSegment::Key(key.to_string())
+    }
+}
+
+/// Parses `alternatives` separated by `sep` as a union of keys, if there are several and none
+/// of them could be part of a child filter.
+fn parse_union(alternatives: &str, sep: char) -> Option<PathSegment> {
+    let is_key = |alt: &str| {
+        !alt.is_empty()
+            && !alt.contains(|c: char| c.is_whitespace() || "(){}!=<>&|,".contains(c) && c != sep)
+    };
+    let alternatives: Vec<&str> = alternatives.split(sep).collect();
+    if alternatives.len() < 2 || !alternatives.iter().all(|alt| is_key(alt)) {
+        return None;
+    }
+    Some(PathSegment::Union(
+        alternatives
+            .into_iter()
+            .map(|alt| match parse_key(alt) {
+                // any key, without the deep recursion of a splat
+                PathSegment::Splat => PathSegment::Glob(SPLAT.to_string()),
+                segment => segment,
+            })
+            .collect(),
+    ))
+}
+
+fn parse_child_filter(filter: &str) -> Result<PathSegment, ParseError> {
+    if filter == "*" {
+        return Ok(PathSegment::Index(ArrayIndices::Star));
+    }
+    if let Some(union) = parse_union(filter, '|') {
+        return Ok(union);
+    }
+    Ok(PathSegment::ChildFilter(parse_filter_expr(filter)?))
+}
+
+fn parse_filter_expr(filter: &str) -> Result<FilterExpr, ParseError> {
+    let mut parser = FilterParser { filter, pos: 0 };
+    let expr = parser.parse_or()?;
+    parser.skip_whitespace();
+    if parser.pos < filter.len() {
+        return Err(parser.invalid());
+    }
+    Ok(expr)
+}
+
+/// Recursive descent parser for the expression inside a child filter's parens.
+struct FilterParser<'f> {
+    filter: &'f str,
+    pos: usize,
+}
+
+impl<'f> FilterParser<'f> {
+    fn invalid(&self) -> ParseError {
+        ParseError::new(&format!("invalid child filter: `{}`", self.filter))
+    }
+
+    fn rest(&self) -> &'f str {
+        &self.filter[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.filter.len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            return true;
+        }
+        false
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, ParseError> {
+        let mut expr = self.parse_and()?;
+        while self.eat("||") {
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, ParseError> {
+        let mut expr = self.parse_unary()?;
+        while self.eat("&&") {
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<FilterExpr, ParseError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        if rest.starts_with('!') && !rest.starts_with("!=") {
+            self.pos += 1;
+            return Ok(FilterExpr::Not(Box::new(self.parse_unary()?)));
+        }
+        if rest.starts_with('(') {
+            let close = matching_paren_close(rest).ok_or_else(|| self.invalid())?;
+            self.pos += close + 1;
+            return parse_filter_expr(&rest[1..close]);
+        }
+        self.parse_condition()
+    }
+
+    /// A condition runs up to the next `&&` or `||`, or the `)` closing the group it is in.
+    fn parse_condition(&mut self) -> Result<FilterExpr, ParseError> {
+        let rest = self.rest();
+        let mut depth = 0;
+        let mut end = rest.len();
+        for (idx, c) in rest.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    end = idx;
+                    break;
+                }
+                ')' => depth -= 1,
+                _ if depth == 0
+                    && (rest[idx..].starts_with("&&") || rest[idx..].starts_with("||")) =>
+                {
+                    end = idx;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let condition = rest[..end].trim();
+        self.pos += end;
+        if let Some(predicate) = self.parse_predicate(condition)? {
+            return Ok(predicate);
+        }
+        let (filter_path, op, filter_value) = split_child_filter(condition)?;
+        if op.is_numeric() && as_number(&Yaml::from_str(filter_value)).is_none() {
+            return Err(ParseError::new(&format!(
+                "invalid child filter: `{}`, `{}` is not a number",
+                self.filter, filter_value
+            )));
+        }
+        Ok(FilterExpr::Cond(ChildFilter {
+            path: parse_path(filter_path)?,
+            op,
+            value: filter_value.to_string(),
+        }))
+    }
+
+    /// Parses `has(path)`, or `type(path)` followed by `==`, `!=` or `=~` and a type name, if
+    /// `condition` starts with one of them.
+    fn parse_predicate(&self, condition: &str) -> Result<Option<FilterExpr>, ParseError> {
+        let (name, args) = match condition.find('(') {
+            Some(idx) if [HAS_PREDICATE, TYPE_PREDICATE].contains(&condition[..idx].trim_end()) => {
+                (condition[..idx].trim_end(), &condition[idx..])
+            }
+            _ => return Ok(None),
+        };
+        let close = matching_paren_close(args).ok_or_else(|| self.invalid())?;
+        let path = parse_path(args[1..close].trim())?;
+        let comparison = args[close + 1..].trim_start();
+        if name == HAS_PREDICATE {
+            if !comparison.is_empty() {
+                return Err(self.invalid());
+            }
+            return Ok(Some(FilterExpr::Has(path)));
+        }
+        let (lhs, op, type_name) = split_child_filter(comparison)?;
+        let is_known_type = match op {
+            FilterOp::Eq | FilterOp::Ne => TYPE_NAMES.contains(&type_name),
+            FilterOp::Match(_) => true,
+            _ => false,
+        };
+        if !lhs.is_empty() || !is_known_type {
+            return Err(ParseError::new(&format!(
+                "invalid child filter: `{}`, expected `{}(path)` to be compared to one of {}",
+                self.filter,
+                TYPE_PREDICATE,
+                TYPE_NAMES.join(", ")
+            )));
+        }
+        Ok(Some(FilterExpr::Type(ChildFilter {
+            path,
+            op,
+            value: type_name.to_string(),
+        })))
+    }
+}
+
+pub fn parse_path(path: &str) -> Result<Vec<PathSegment>, ParseError> {
+    let mut parsed_path: Vec<PathSegment> = vec![];
     let mut current_idx = 0;
     while current_idx < path.len() {
+        if path[current_idx..].starts_with(KEY_REGEX_PREFIX) {
+            if let Some((segment, len)) = parse_key_regex(&path[current_idx..])? {
+                parsed_path.push(segment);
+                current_idx += len;
+                continue;
+            }
+        }
         match next_special_char_is(&path[current_idx..]) {
             (PathElem::Dot, relative_dot_idx) => {
                 let dot_idx = current_idx + relative_dot_idx;
@@ -84,7 +628,7 @@ pub fn parse_path(path: &str) -> Result<Vec<String>, ParseError> {
                     current_idx += 1;
                     continue;
                 }
-                parsed_path.push(path[current_idx..dot_idx].to_string());
+                parsed_path.push(parse_key(&path[current_idx..dot_idx]));
                 current_idx = dot_idx + 1;
             }
             (PathElem::Quote, relative_start_quote_idx) => {
@@ -93,7 +637,10 @@ pub fn parse_path(path: &str) -> Result<Vec<String>, ParseError> {
                     next_specific_special_char(&path[start_quoted_word_idx..], PathElem::Quote);
                 if found {
                     let end_quote_idx = start_quoted_word_idx + relative_end_quote_idx;
-                    parsed_path.push(path[start_quoted_word_idx..end_quote_idx].to_string());
+                    // quoted keys are always matched literally
+                    parsed_path.push(PathSegment::Key(
+                        path[start_quoted_word_idx..end_quote_idx].to_string(),
+                    ));
                     current_idx = end_quote_idx + 1;
                 } else {
                     return Err(ParseError::new(&format!(
@@ -104,14 +651,24 @@ pub fn parse_path(path: &str) -> Result<Vec<String>, ParseError> {
             }
             (PathElem::ArrayOpen, relative_array_open_idx) => {
                 let array_open_idx = current_idx + relative_array_open_idx;
+                if path[current_idx..array_open_idx].ends_with('\\') {
+                    return Err(ParseError::new(&format!(
+                        "invalid path `{}`, `[` always starts an array index in a key, character \
+                         classes are only supported in child filter values, quote the key to match \
+                         `[` literally",
+                        path
+                    )));
+                }
                 if array_open_idx != current_idx {
-                    parsed_path.push(path[current_idx..array_open_idx].to_string());
+                    parsed_path.push(parse_key(&path[current_idx..array_open_idx]));
                 }
                 let (found, relative_array_close_idx) =
                     next_specific_special_char(&path[array_open_idx..], PathElem::ArrayClose);
                 if found {
                     let array_close_idx = array_open_idx + relative_array_close_idx;
-                    parsed_path.push(path[array_open_idx..array_close_idx + 1].to_string());
+                    parsed_path.push(PathSegment::Index(parse_array_indexing_operation(
+                        &path[array_open_idx + 1..array_close_idx],
+                    )?));
                     current_idx = array_close_idx + 1;
                 } else {
                     return Err(ParseError::new(&format!(
@@ -123,13 +680,15 @@ pub fn parse_path(path: &str) -> Result<Vec<String>, ParseError> {
             (PathElem::ParenOpen, relative_paren_open_idx) => {
                 let paren_open_idx = current_idx + relative_paren_open_idx;
                 if paren_open_idx != current_idx {
-                    parsed_path.push(path[current_idx..paren_open_idx].to_string());
+                    parsed_path.push(parse_key(&path[current_idx..paren_open_idx]));
                 }
-                let (found, relative_paren_close_idx) =
-                    next_specific_special_char(&path[paren_open_idx..], PathElem::ParenClose);
-                if found {
+                if let Some(relative_paren_close_idx) =
+                    matching_paren_close(&path[paren_open_idx..])
+                {
                     let paren_close_idx = paren_open_idx + relative_paren_close_idx;
-                    parsed_path.push(path[paren_open_idx..paren_close_idx + 1].to_string());
+                    parsed_path.push(parse_child_filter(
+                        &path[paren_open_idx + 1..paren_close_idx],
+                    )?);
                     current_idx = paren_close_idx + 1;
                 } else {
                     return Err(ParseError::new(&format!(
@@ -150,8 +709,8 @@ pub fn parse_path(path: &str) -> Result<Vec<String>, ParseError> {
                     path
                 )));
             }
-            (PathElem::EOW, _) => {
-                parsed_path.push(path[current_idx..].to_string());
+            (PathElem::Eow, _) => {
+                parsed_path.push(parse_key(&path[current_idx..]));
                 break;
             }
             _ => {
@@ -162,124 +721,342 @@ pub fn parse_path(path: &str) -> Result<Vec<String>, ParseError> {
     Ok(parsed_path)
 }
 
-pub fn split_child_filter(filter: &str) -> Result<[&str; 2], ParseError> {
-    if !filter.contains(CHILD_FILTER_DELIM) {
-        return Err(ParseError::new(&format!(
-            "invalid child filter: `{}`",
-            filter
-        )));
-    }
-    let split_filter: Vec<&str> = filter.split(CHILD_FILTER_DELIM).collect();
-    if split_filter.len() != 2 {
-        return Err(ParseError::new(&format!(
-            "invalid child filter: `{}`",
-            filter
-        )));
+/// Splits a child filter condition at its first operator into the child path, the operator and
+/// the value, which may itself contain operators. Whitespace around the operator is ignored, and
+/// the value of `=~` is compiled as a regular expression.
+pub fn split_child_filter(filter: &str) -> Result<(&str, FilterOp, &str), ParseError> {
+    let invalid = || ParseError::new(&format!("invalid child filter: `{}`", filter));
+    for (idx, _) in filter.char_indices() {
+        if let Some(delim) = FILTER_OPS
+            .iter()
+            .find(|delim| filter[idx..].starts_with(*delim))
+        {
+            let value = filter[idx + delim.len()..].trim_start();
+            let op = match *delim {
+                CHILD_FILTER_DELIM => FilterOp::Eq,
+                REGEX_FILTER_DELIM => FilterOp::Match(PathRegex::new(value)?),
+                "!=" => FilterOp::Ne,
+                "<=" => FilterOp::Le,
+                ">=" => FilterOp::Ge,
+                "<" => FilterOp::Lt,
+                _ => FilterOp::Gt,
+            };
+            return Ok((filter[..idx].trim_end(), op, value));
+        }
     }
-    let mut split_filter_array = ["", ""];
-    split_filter_array.copy_from_slice(&split_filter);
-    Ok(split_filter_array)
+    Err(invalid())
 }
 
-pub fn parse_array_child_filter(
-    path_elem: &str,
-    array_node: &Vec<Yaml>,
+pub fn apply_array_child_filter(
+    filter: &FilterExpr,
+    array_node: &[Yaml],
     is_final_path_elem: bool,
-) -> Result<ArrayIndices, ParseError> {
-    if path_elem == "*" {
-        return Ok(ArrayIndices::Star);
-    }
-    let filter_key_and_value = crate::path::split_child_filter(path_elem)?;
-    let (filter_path, filter_value) = (filter_key_and_value[0], filter_key_and_value[1]);
-
-    // parse filter_path
-    let parsed_path = parse_path(filter_path)?;
-    debug!("parsed path for child filtering: {:?}", parsed_path);
+) -> Result<ArrayIndices, Error> {
+    debug!("parsed child filter: {:?}", filter);
 
     let mut indices: Vec<usize> = vec![];
+    for (idx, array_elem) in array_node.iter().enumerate() {
+        if is_child_filter_match(array_elem, filter, is_final_path_elem)? {
+            indices.push(idx);
+        }
+    }
+    debug!("child filtering matched indices: {:?}", indices);
+    Ok(ArrayIndices::Indices(indices))
+}
 
-    if is_final_path_elem {
-        // child value filter
-        debug!("running a child value filter");
-        for (idx, array_elem) in array_node.iter().enumerate() {
-            if matches_pattern(
-                &crate::convert::convert_single_node(array_elem),
-                filter_value,
-            ) {
-                debug!("array_elem matched child value filter: {:?}", array_elem);
-                indices.push(idx);
-            }
+/// Whether a child filter selects `node`, an array element or map value.
+pub fn is_child_filter_match(
+    node: &Yaml,
+    filter: &FilterExpr,
+    is_final_path_elem: bool,
+) -> Result<bool, Error> {
+    Ok(match filter {
+        FilterExpr::Cond(cond) => is_condition_match(node, cond, is_final_path_elem)?,
+        FilterExpr::Has(path) => {
+            let mut visited = Vec::<VisitedNode>::new();
+            traverse(node, path, &mut visited)?;
+            !visited.is_empty()
         }
-        debug!("child value filtering matched indices: {:?}", indices);
-    } else {
-        // run a traverse search again against each node to determine if this is a valid child path
-        debug!("running a child node filter");
-        for (idx, array_elem) in array_node.iter().enumerate() {
+        FilterExpr::Type(cond) => {
             let mut visited = Vec::<VisitedNode>::new();
-            traverse(
-                array_elem,
-                "",
-                &parsed_path,
-                String::new(),
-                false,
-                &mut visited,
-            );
-            if visited.len() != 1 {
-                debug!(
-                    "array_elem did not match child node filter, continuing: {:?}",
-                    array_elem
-                );
-                continue;
-            }
-            let ref visited_elem = visited[0];
-            if matches_pattern(
-                &crate::convert::convert_single_node(visited_elem.yml),
-                filter_value, // path element for child filter
-            ) {
-                debug!("array_elem matched child node filter: {:?}", array_elem);
-                indices.push(idx);
-            }
+            traverse(node, &cond.path, &mut visited)?;
+            visited.len() == 1
+                && is_child_filter_value_match(
+                    &Yaml::String(type_name(visited[0].yml).to_string()),
+                    cond,
+                )?
+        }
+        FilterExpr::Not(expr) => !is_child_filter_match(node, expr, is_final_path_elem)?,
+        FilterExpr::And(a, b) => {
+            is_child_filter_match(node, a, is_final_path_elem)?
+                && is_child_filter_match(node, b, is_final_path_elem)?
+        }
+        FilterExpr::Or(a, b) => {
+            is_child_filter_match(node, a, is_final_path_elem)?
+                || is_child_filter_match(node, b, is_final_path_elem)?
         }
-        debug!("child node filtering matched indices: {:?}", indices);
+    })
+}
+
+/// The name `type(path)` gives `node`, one of `TYPE_NAMES`.
+pub fn type_name(node: &Yaml) -> &'static str {
+    match node {
+        Yaml::Hash(_) => "map",
+        Yaml::Array(_) => "seq",
+        Yaml::String(_) => "string",
+        Yaml::Integer(_) => "int",
+        Yaml::Real(_) => "float",
+        Yaml::Boolean(_) => "bool",
+        _ => "null",
     }
-    Ok(ArrayIndices::Indices(indices))
 }
 
+/// Whether a single condition selects `node`. A final condition without a child path, e.g.
+/// `(.==crab)`, matches on the node's own value. Otherwise the child path is searched for in the
+/// node, and has to lead to exactly one matching value.
+fn is_condition_match(
+    node: &Yaml,
+    filter: &ChildFilter,
+    is_final_path_elem: bool,
+) -> Result<bool, Error> {
+    if is_final_path_elem && filter.path.is_empty() {
+        // child value filter, e.g. `(.==crab)`
+        let is_match = is_child_filter_value_match(node, filter)?;
+        if is_match {
+            debug!("node matched child value filter: {:?}", node);
+        }
+        return Ok(is_match);
+    }
+    // run a traverse search again against the node to determine if this is a valid child path
+    let mut visited = Vec::<VisitedNode>::new();
+    traverse(node, &filter.path, &mut visited)?;
+    if visited.len() != 1 {
+        debug!(
+            "node did not match child node filter, continuing: {:?}",
+            node
+        );
+        return Ok(false);
+    }
+    let is_match = is_child_filter_value_match(visited[0].yml, filter)?;
+    if is_match {
+        debug!("node matched child node filter: {:?}", node);
+    }
+    Ok(is_match)
+}
+
+/// Parses the inside of `[...]`: `*`, or a comma separated list of indices and slices. Plain
+/// non-negative indices give `Indices`, anything needing the array's length `Relative`.
 pub fn parse_array_indexing_operation(path_elem: &str) -> Result<ArrayIndices, ParseError> {
     if path_elem == "*" {
         return Ok(ArrayIndices::Star);
     }
-    match path_elem.parse::<usize>() {
-        Ok(i) => Ok(ArrayIndices::Indices(vec![i])),
-        Err(e) => Err(ParseError(format!(
-            "unable to parse array index `{:?}`, error: {:?}",
-            path_elem, e
-        ))),
+    let selectors = path_elem
+        .split(',')
+        .map(|selector| parse_index_selector(selector.trim()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            ParseError(format!(
+                "unable to parse array index `{:?}`, error: {}",
+                path_elem, e
+            ))
+        })?;
+    let mut indices = Vec::with_capacity(selectors.len());
+    for selector in &selectors {
+        match selector {
+            IndexSelector::At(i) if *i >= 0 => indices.push(*i as usize),
+            _ => return Ok(ArrayIndices::Relative(selectors)),
+        }
+    }
+    Ok(ArrayIndices::Indices(indices))
+}
+
+fn parse_index_selector(selector: &str) -> Result<IndexSelector, String> {
+    let parse_bound = |bound: &str| -> Result<Option<i64>, String> {
+        let bound = bound.trim();
+        if bound.is_empty() {
+            return Ok(None);
+        }
+        bound
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|e| format!("{:?}", e))
+    };
+    let parts: Vec<&str> = selector.split(':').collect();
+    match parts[..] {
+        [i] => i
+            .parse::<i64>()
+            .map(IndexSelector::At)
+            .map_err(|e| format!("{:?}", e)),
+        [start, end] | [start, end, ""] => Ok(IndexSelector::Slice {
+            start: parse_bound(start)?,
+            end: parse_bound(end)?,
+            step: 1,
+        }),
+        [start, end, step] => match parse_bound(step)? {
+            Some(0) => Err("slice step cannot be zero".to_string()),
+            step => Ok(IndexSelector::Slice {
+                start: parse_bound(start)?,
+                end: parse_bound(end)?,
+                step: step.unwrap_or(1),
+            }),
+        },
+        _ => Err("too many `:` in slice".to_string()),
+    }
+}
+
+/// One element of a glob pattern.
+#[derive(Debug, PartialEq)]
+enum GlobToken {
+    /// `*`, any run of chars
+    Any,
+    /// `?`, exactly one char
+    One,
+    /// `[abc]`, `[a-z]` or a negated `[!abc]`, one char in (or out of) the set
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Literal(char),
+}
+
+impl GlobToken {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            GlobToken::Any | GlobToken::One => true,
+            GlobToken::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated
+            }
+            GlobToken::Literal(l) => *l == c,
+        }
     }
 }
 
+/// Splits a glob pattern into tokens. `\` escapes the char after it, and a `[` without a
+/// closing `]` is a literal.
+fn glob_tokens(pattern: &str) -> Vec<GlobToken> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        let token = match chars[idx] {
+            '*' => GlobToken::Any,
+            '?' => GlobToken::One,
+            '\\' if idx + 1 < chars.len() => {
+                idx += 1;
+                GlobToken::Literal(chars[idx])
+            }
+            '[' => match glob_class(&chars[idx + 1..]) {
+                Some((token, len)) => {
+                    idx += len;
+                    token
+                }
+                None => GlobToken::Literal('['),
+            },
+            c => GlobToken::Literal(c),
+        };
+        tokens.push(token);
+        idx += 1;
+    }
+    tokens
+}
+
+/// Parses the body of a `[...]` class, returning it with the number of chars it spans
+/// including the closing `]`. A `]` right after the opening bracket is part of the set.
+fn glob_class(chars: &[char]) -> Option<(GlobToken, usize)> {
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    let mut idx = if negated { 1 } else { 0 };
+    let body_start = idx;
+    let mut ranges = Vec::new();
+    while idx < chars.len() {
+        let c = chars[idx];
+        if c == ']' && idx > body_start {
+            return Some((GlobToken::Class { negated, ranges }, idx + 1));
+        }
+        if idx + 2 < chars.len() && chars[idx + 1] == '-' && chars[idx + 2] != ']' {
+            ranges.push((c, chars[idx + 2]));
+            idx += 3;
+        } else {
+            ranges.push((c, c));
+            idx += 1;
+        }
+    }
+    None
+}
+
+/// Matches `v` against a glob pattern supporting `*`, `?`, `[...]` classes and `\` escapes.
 pub fn matches_pattern(v: &str, pattern: &str) -> bool {
-    if v == pattern || pattern == SPLAT {
-        return true;
+    if !pattern.contains(&['*', '?', '[', '\\'][..]) {
+        return v == pattern;
     }
-    if pattern.ends_with('*') {
-        let truncated_p = pattern.trim_end_matches('*');
-        if v.starts_with(truncated_p) {
-            return true;
+    let tokens = glob_tokens(pattern);
+    let chars: Vec<char> = v.chars().collect();
+    let (mut t_idx, mut c_idx) = (0, 0);
+    // where to resume after the last `*` if the rest fails to match: its token and the char
+    // it currently stops consuming at
+    let mut backtrack = None;
+    while c_idx < chars.len() {
+        match tokens.get(t_idx) {
+            Some(GlobToken::Any) => {
+                t_idx += 1;
+                backtrack = Some((t_idx, c_idx));
+            }
+            Some(token) if token.matches(chars[c_idx]) => {
+                t_idx += 1;
+                c_idx += 1;
+            }
+            _ => match backtrack {
+                Some((star_t_idx, star_c_idx)) => {
+                    t_idx = star_t_idx;
+                    c_idx = star_c_idx + 1;
+                    backtrack = Some((star_t_idx, c_idx));
+                }
+                None => return false,
+            },
         }
     }
-    false
+    tokens[t_idx..].iter().all(|token| *token == GlobToken::Any)
 }
 
 pub fn is_child_filter(p: &str) -> bool {
     p.starts_with('(') && p.ends_with(')')
 }
 
-pub fn is_child_filter_value_match(v: &Yaml, pattern: &str) -> Result<bool, ParseError> {
-    let filter_key_and_value = split_child_filter(pattern)?;
-    let v_str = &crate::convert::convert_single_node(v);
-    let filter_value = filter_key_and_value[1];
-    Ok(matches_pattern(v_str, filter_value))
+pub fn is_child_filter_value_match(v: &Yaml, filter: &ChildFilter) -> Result<bool, Error> {
+    let ordering = match &filter.op {
+        FilterOp::Eq | FilterOp::Ne => {
+            let v_str = &crate::convert::convert_single_node(v)?;
+            return Ok(matches_pattern(v_str, &filter.value) == (filter.op == FilterOp::Eq));
+        }
+        FilterOp::Match(re) => {
+            return Ok(re.is_match(&crate::convert::convert_single_node(v)?));
+        }
+        _ => match compare_numbers(v, &Yaml::from_str(&filter.value)) {
+            Some(ordering) => ordering,
+            None => return Ok(false),
+        },
+    };
+    Ok(match filter.op {
+        FilterOp::Lt => ordering == Ordering::Less,
+        FilterOp::Le => ordering != Ordering::Greater,
+        FilterOp::Gt => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Less,
+    })
+}
+
+fn as_number(v: &Yaml) -> Option<f64> {
+    match v {
+        Yaml::Integer(i) => Some(*i as f64),
+        Yaml::Real(_) => v.as_f64(),
+        _ => None,
+    }
+}
+
+/// Compares two numbers, as integers when both are to stay exact.
+fn compare_numbers(a: &Yaml, b: &Yaml) -> Option<Ordering> {
+    match (a, b) {
+        (Yaml::Integer(a), Yaml::Integer(b)) => Some(a.cmp(b)),
+        _ => as_number(a)?.partial_cmp(&as_number(b)?),
+    }
 }
 
 #[cfg(test)]
@@ -305,7 +1082,8 @@ mod tests {
         assert_eq!((PathElem::ArrayClose, 4), next_special_char_is("asdf]asdf"));
         assert_eq!((PathElem::ParenOpen, 4), next_special_char_is("asdf(asdf"));
         assert_eq!((PathElem::ParenClose, 4), next_special_char_is("asdf)asdf"));
-        assert_eq!((PathElem::EOW, 8), next_special_char_is("asdfasdf"));
+        assert_eq!((PathElem::Eow, 8), next_special_char_is("asdfasdf"));
+        assert_eq!((PathElem::Dot, 2), next_special_char_is("é.x"));
     }
 
     #[test]
@@ -384,7 +1162,7 @@ mod tests {
         );
         assert_eq!(
             (true, 8),
-            next_specific_special_char("asdfasdf", PathElem::EOW)
+            next_specific_special_char("asdfasdf", PathElem::Eow)
         );
     }
 
@@ -408,6 +1186,44 @@ mod tests {
         assert!(!matches_pattern("rusty", "smooth"));
     }
 
+    #[test]
+    fn test_matches_pattern_infix_and_leading_splat() {
+        assert!(matches_pattern("first_name", "*_name"));
+        assert!(matches_pattern("a_to_b", "a*b"));
+        assert!(matches_pattern("abcbd", "a*b*d"));
+        assert!(matches_pattern("ab", "a*b"));
+        assert!(!matches_pattern("a_to_c", "a*b"));
+        assert!(!matches_pattern("name_first", "*_name"));
+    }
+
+    #[test]
+    fn test_matches_pattern_single_char() {
+        assert!(matches_pattern("item1", "item?"));
+        assert!(matches_pattern("itemé", "item?"));
+        assert!(!matches_pattern("item", "item?"));
+        assert!(!matches_pattern("item12", "item?"));
+    }
+
+    #[test]
+    fn test_matches_pattern_class() {
+        assert!(matches_pattern("web-b", "web-[abc]"));
+        assert!(!matches_pattern("web-d", "web-[abc]"));
+        assert!(matches_pattern("web-7", "web-[0-9]"));
+        assert!(matches_pattern("web-d", "web-[!abc]"));
+        assert!(!matches_pattern("web-a", "web-[^abc]"));
+        assert!(matches_pattern("]", "[]]"));
+        // an unclosed class is a literal `[`
+        assert!(matches_pattern("a[b", "a[b"));
+    }
+
+    #[test]
+    fn test_matches_pattern_escaped() {
+        assert!(matches_pattern("a*", "a\\*"));
+        assert!(!matches_pattern("ab", "a\\*"));
+        assert!(matches_pattern("a?", "a\\?"));
+        assert!(!matches_pattern("ab", "a\\?"));
+    }
+
     #[test]
     fn test_is_child_filter_no() {
         assert!(!is_child_filter("[.==crabby]"));
@@ -423,59 +1239,280 @@ mod tests {
         assert!(is_child_filter("()"));
     }
 
+    fn filter_expr(filter: &str) -> FilterExpr {
+        match parse_child_filter(filter).unwrap() {
+            PathSegment::ChildFilter(f) => f,
+            s => panic!("not a child filter: {:?}", s),
+        }
+    }
+
+    fn child_filter(filter: &str) -> ChildFilter {
+        match filter_expr(filter) {
+            FilterExpr::Cond(f) => f,
+            f => panic!("not a single condition: {:?}", f),
+        }
+    }
+
     #[test]
-    fn test_is_child_filter_value_match_not_filter_errs() {
+    fn test_parse_child_filter_not_filter_errs() {
+        assert!(parse_child_filter("crabby").is_err());
+    }
+
+    #[test]
+    fn test_parse_child_filter_star() {
         assert_eq!(
-            true,
-            is_child_filter_value_match(&Yaml::String("crabby".to_string()), "crabby").is_err()
+            PathSegment::Index(ArrayIndices::Star),
+            parse_child_filter("*").unwrap()
         );
     }
 
     #[test]
-    fn test_is_child_filter_value_match_not_a_match() {
-        assert!(
-            !is_child_filter_value_match(&Yaml::String("crabby".to_string()), "(.==nope)").unwrap()
+    fn test_parse_key() {
+        assert_eq!(PathSegment::Key("crab".to_string()), parse_key("crab"));
+        assert_eq!(PathSegment::Glob("crab*".to_string()), parse_key("crab*"));
+        assert_eq!(PathSegment::Glob("*".to_string()), parse_key("*"));
+        assert_eq!(PathSegment::Glob("*_id?".to_string()), parse_key("*_id?"));
+        assert_eq!(PathSegment::Splat, parse_key("**"));
+    }
+
+    #[test]
+    fn test_parse_union() {
+        let union = PathSegment::Union(vec![
+            PathSegment::Key("b".to_string()),
+            PathSegment::Glob("c*".to_string()),
+        ]);
+        assert_eq!(parse_key("{b,c*}"), union);
+        assert_eq!(parse_child_filter("b|c*").unwrap(), union);
+        assert_eq!(
+            parse_key("{b,**}"),
+            PathSegment::Union(vec![
+                PathSegment::Key("b".to_string()),
+                PathSegment::Glob("**".to_string())
+            ])
         );
+        // a single key or anything a filter could mean is not a union
+        assert_eq!(parse_key("{b}"), PathSegment::Key("{b}".to_string()));
+        assert_eq!(parse_key("{b,}"), PathSegment::Key("{b,}".to_string()));
+        assert_eq!(parse_union("a==b|c", '|'), None);
+        assert_eq!(parse_union("a || b", '|'), None);
+        assert_eq!(parse_union("a||b", '|'), None);
     }
 
     #[test]
-    fn test_is_child_filter_value_match_is_a_match() {
-        assert!(
-            is_child_filter_value_match(&Yaml::String("crabby".to_string()), ".==crabby").unwrap()
+    fn test_parse_key_regex() {
+        let (segment, len) = parse_key_regex(r"~/^item_\d+$/.a").unwrap().unwrap();
+        assert_eq!(
+            segment,
+            PathSegment::Regex(PathRegex::new(r"^item_\d+$").unwrap())
         );
+        assert_eq!(len, 13);
+        let (segment, _) = parse_key_regex(r"~/a\/b/").unwrap().unwrap();
+        assert_eq!(segment, PathSegment::Regex(PathRegex::new("a/b").unwrap()));
+        // keys that only look like they start a regex stay keys
+        assert_eq!(parse_key_regex("/api/").unwrap(), None);
+        assert_eq!(parse_key_regex("~/a/b").unwrap(), None);
+        assert_eq!(parse_key_regex("~/a").unwrap(), None);
+        assert!(parse_key_regex("~/a(/").is_err());
+    }
+
+    #[test]
+    fn test_is_child_filter_value_match_regex() {
+        let filter = child_filter(".=~^web-[0-9]+$");
+        assert!(is_child_filter_value_match(&Yaml::String("web-12".to_string()), &filter).unwrap());
+        assert!(!is_child_filter_value_match(&Yaml::String("web-x".to_string()), &filter).unwrap());
+        assert!(is_child_filter_value_match(&Yaml::Integer(7), &child_filter(".=~^\\d$")).unwrap());
+    }
+
+    #[test]
+    fn test_is_child_filter_value_match_not_a_match() {
+        assert!(!is_child_filter_value_match(
+            &Yaml::String("crabby".to_string()),
+            &child_filter(".==nope")
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_is_child_filter_value_match_is_a_match() {
+        assert!(is_child_filter_value_match(
+            &Yaml::String("crabby".to_string()),
+            &child_filter(".==crabby")
+        )
+        .unwrap());
+        assert!(is_child_filter_value_match(
+            &Yaml::String("crabby".to_string()),
+            &child_filter(".==crab*")
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_is_child_filter_value_match_not_equal() {
+        let filter = child_filter(".!=crab*");
         assert!(
-            is_child_filter_value_match(&Yaml::String("crabby".to_string()), ".==crab*").unwrap()
+            !is_child_filter_value_match(&Yaml::String("crabby".to_string()), &filter).unwrap()
         );
+        assert!(is_child_filter_value_match(&Yaml::String("fish".to_string()), &filter).unwrap());
+    }
+
+    #[test]
+    fn test_is_child_filter_value_match_numeric() {
+        let int = Yaml::Integer(300);
+        let real = Yaml::Real("299.5".to_string());
+        assert!(!is_child_filter_value_match(&int, &child_filter(".>300")).unwrap());
+        assert!(is_child_filter_value_match(&int, &child_filter(".>=300")).unwrap());
+        assert!(is_child_filter_value_match(&int, &child_filter(".<300.5")).unwrap());
+        assert!(!is_child_filter_value_match(&int, &child_filter(".<=299")).unwrap());
+        assert!(is_child_filter_value_match(&real, &child_filter(".<300")).unwrap());
+        assert!(is_child_filter_value_match(&real, &child_filter(".>-1")).unwrap());
+        // strings are never compared numerically, even when they look like numbers
+        assert!(!is_child_filter_value_match(
+            &Yaml::String("400".to_string()),
+            &child_filter(".>300")
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_parse_child_filter_numeric_needs_number() {
+        assert!(parse_child_filter("timeout>abc").is_err());
+        assert!(parse_child_filter("timeout>1e3").is_ok());
     }
 
     #[test]
     fn test_split_child_filter_valid() {
-        let split_filter = split_child_filter(".==crabby").unwrap();
-        assert_eq!(split_filter[0], ".");
-        assert_eq!(split_filter[1], "crabby");
+        assert_eq!(
+            split_child_filter(".==crabby").unwrap(),
+            (".", FilterOp::Eq, "crabby")
+        );
+        assert_eq!(
+            split_child_filter("a.b!=x").unwrap(),
+            ("a.b", FilterOp::Ne, "x")
+        );
+        assert_eq!(
+            split_child_filter("timeout<=300").unwrap(),
+            ("timeout", FilterOp::Le, "300")
+        );
+        assert_eq!(
+            split_child_filter("timeout>-1.5").unwrap(),
+            ("timeout", FilterOp::Gt, "-1.5")
+        );
+        assert_eq!(
+            split_child_filter("a == b==c").unwrap(),
+            ("a", FilterOp::Eq, "b==c")
+        );
+        assert_eq!(
+            split_child_filter("name=~^(a|b)=$").unwrap(),
+            (
+                "name",
+                FilterOp::Match(PathRegex::new("^(a|b)=$").unwrap()),
+                "^(a|b)=$"
+            )
+        );
     }
 
     #[test]
-    fn test_split_child_filter_invalid() {
-        assert_eq!(true, split_child_filter(".=crabby").is_err());
-        assert_eq!(true, split_child_filter("").is_err());
+    fn test_parse_filter_expr_precedence() {
+        let cond = |s: &str| Box::new(FilterExpr::Cond(child_filter(s)));
+        assert_eq!(
+            filter_expr("a==1 || b==2 && !c==3"),
+            FilterExpr::Or(
+                cond("a==1"),
+                Box::new(FilterExpr::And(
+                    cond("b==2"),
+                    Box::new(FilterExpr::Not(cond("c==3")))
+                ))
+            )
+        );
+        assert_eq!(
+            filter_expr("!(a==1 || b!=2) && c=~^(x|y)$"),
+            FilterExpr::And(
+                Box::new(FilterExpr::Not(Box::new(FilterExpr::Or(
+                    cond("a==1"),
+                    cond("b!=2")
+                )))),
+                cond("c=~^(x|y)$")
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_filter_expr_invalid() {
+        assert!(parse_child_filter("a==1 &&").is_err());
+        assert!(parse_child_filter("|| a==1").is_err());
+        assert!(parse_child_filter("(a==1").is_err());
+        assert!(parse_child_filter("a==1 && b").is_err());
+        assert!(parse_child_filter("!").is_err());
     }
 
     #[test]
-    fn test_parse_array_child_filter_star() {
+    fn test_is_child_filter_match_combinators() {
+        use yaml_rust::YamlLoader;
+        let node =
+            &YamlLoader::load_from_str("{kind: Deployment, ns: default, replicas: 3}").unwrap()[0];
+        let is_match = |filter: &str| is_child_filter_match(node, &filter_expr(filter), false);
+        assert!(is_match("kind==Deployment && ns!=kube-system").unwrap());
+        assert!(!is_match("kind==Deployment && ns==kube-system").unwrap());
+        assert!(is_match("kind==Pod || replicas>2").unwrap());
+        assert!(is_match("!(kind==Pod || ns==kube-*)").unwrap());
+        assert!(!is_match("!kind==Deployment").unwrap());
+    }
+
+    #[test]
+    fn test_parse_filter_expr_predicates() {
         assert_eq!(
-            ArrayIndices::Star,
-            parse_array_child_filter("*", &vec![Yaml::Null], false).unwrap()
+            filter_expr("!has(resources.limits)"),
+            FilterExpr::Not(Box::new(FilterExpr::Has(vec![
+                PathSegment::Key("resources".to_string()),
+                PathSegment::Key("limits".to_string())
+            ])))
         );
+        assert_eq!(
+            filter_expr("type(.) != map"),
+            FilterExpr::Type(ChildFilter {
+                path: vec![],
+                op: FilterOp::Ne,
+                value: "map".to_string()
+            })
+        );
+        assert!(parse_child_filter("type(.)==mapping").is_err());
+        assert!(parse_child_filter("type(.)>1").is_err());
+        assert!(parse_child_filter("type(.)").is_err());
+        assert!(parse_child_filter("has(a)==1").is_err());
+        assert!(parse_child_filter("has(a").is_err());
+    }
+
+    #[test]
+    fn test_is_child_filter_match_predicates() {
+        use yaml_rust::YamlLoader;
+        let node = &YamlLoader::load_from_str("{a: {b: [1, 2]}, c: 1.5, d: ~}").unwrap()[0];
+        let is_match = |filter: &str| is_child_filter_match(node, &filter_expr(filter), false);
+        assert!(is_match("has(a.b)").unwrap());
+        assert!(is_match("has(a.b[*])").unwrap());
+        assert!(!is_match("has(a.x)").unwrap());
+        assert!(is_match("has(d)").unwrap());
+        assert!(is_match("type(.)==map").unwrap());
+        assert!(is_match("type(a.b)==seq && type(c)==float && type(d)==null").unwrap());
+        assert!(is_match("type(c)=~^(int|float)$").unwrap());
+        // more than one node has no single type
+        assert!(!is_match("type(a.b[*])==int").unwrap());
+        assert!(!is_child_filter_match(&Yaml::Integer(1), &filter_expr("has(a)"), true).unwrap());
+    }
+
+    #[test]
+    fn test_split_child_filter_invalid() {
+        assert!(split_child_filter(".=crabby").is_err());
+        assert!(split_child_filter("").is_err());
+        assert!(split_child_filter("name=~[").is_err());
     }
 
     #[test]
-    fn test_parse_array_child_filter_final() {
+    fn test_apply_array_child_filter_final() {
         assert_eq!(
             ArrayIndices::Indices(vec![0, 2]),
-            parse_array_child_filter(
-                ".==dog*",
-                &vec![
+            apply_array_child_filter(
+                &filter_expr(".==dog*"),
+                &[
                     Yaml::String("dog".to_string()),
                     Yaml::String("cat".to_string()),
                     Yaml::String("doggerino".to_string())
@@ -487,7 +1524,7 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_array_child_filter_node() {
+    fn test_apply_array_child_filter_node() {
         use yaml_rust::YamlLoader;
         let docs_str = "
 - b:
@@ -499,7 +1536,7 @@ mod tests {
 - b:
     a3: 3
     d: doggerino";
-        let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
+        let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
 
         let array = match doc {
             Yaml::Array(v) => v,
@@ -507,22 +1544,12 @@ mod tests {
         };
         assert_eq!(
             ArrayIndices::Indices(vec![0, 2]),
-            parse_array_child_filter("b.d==dog*", array, false).unwrap()
+            apply_array_child_filter(&filter_expr("b.d==dog*"), array, false).unwrap()
+        );
+        assert_eq!(
+            ArrayIndices::Indices(vec![0, 2]),
+            apply_array_child_filter(&filter_expr("b.d==dog*"), array, true).unwrap()
         );
-    }
-
-    #[test]
-    fn test_parse_array_child_filter_invalid() {
-        use yaml_rust::YamlLoader;
-        let docs_str = "
-- b";
-        let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
-
-        let array = match doc {
-            Yaml::Array(v) => v,
-            _ => panic!("invalid doc, not an array"),
-        };
-        assert_eq!(true, parse_array_child_filter(".=b", array, false).is_err());
     }
 
     #[test]
@@ -541,8 +1568,110 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_array_indexing_operation_list_and_slices() {
+        assert_eq!(
+            ArrayIndices::Indices(vec![0, 2, 5]),
+            parse_array_indexing_operation("0, 2,5").unwrap()
+        );
+        assert_eq!(
+            ArrayIndices::Relative(vec![
+                IndexSelector::At(-1),
+                IndexSelector::Slice {
+                    start: Some(1),
+                    end: None,
+                    step: 1
+                },
+                IndexSelector::Slice {
+                    start: None,
+                    end: Some(-2),
+                    step: -2
+                }
+            ]),
+            parse_array_indexing_operation("-1,1:,:-2:-2").unwrap()
+        );
+    }
+
     #[test]
     fn test_parse_array_indexing_operation_fails_invalid() {
-        assert_eq!(true, parse_array_indexing_operation("a").is_err());
+        assert!(parse_array_indexing_operation("a").is_err());
+        assert!(parse_array_indexing_operation("1,").is_err());
+        assert!(parse_array_indexing_operation("::0").is_err());
+        assert!(parse_array_indexing_operation("1:2:3:4").is_err());
+    }
+
+    #[test]
+    fn test_resolve_indices() {
+        let resolve = |s: &str| parse_array_indexing_operation(s).unwrap().resolve(5);
+        assert_eq!(resolve("*"), (vec![0, 1, 2, 3, 4], vec![]));
+        assert_eq!(resolve("-1"), (vec![4], vec![]));
+        assert_eq!(resolve("-5"), (vec![0], vec![]));
+        assert_eq!(resolve("1:3"), (vec![1, 2], vec![]));
+        assert_eq!(resolve(":2"), (vec![0, 1], vec![]));
+        assert_eq!(resolve("::2"), (vec![0, 2, 4], vec![]));
+        assert_eq!(resolve("-2:"), (vec![3, 4], vec![]));
+        assert_eq!(resolve("3:100"), (vec![3, 4], vec![]));
+        assert_eq!(resolve("-100:1"), (vec![0], vec![]));
+        assert_eq!(resolve("3:1"), (vec![], vec![]));
+        assert_eq!(resolve("::-1"), (vec![4, 3, 2, 1, 0], vec![]));
+        assert_eq!(resolve("3:0:-2"), (vec![3, 1], vec![]));
+        assert_eq!(resolve("0,-1"), (vec![0, 4], vec![]));
+    }
+
+    #[test]
+    fn test_may_select_settles_within_reach() {
+        let indices = |s: &str| parse_array_indexing_operation(s).unwrap();
+        for selection in [
+            "-1", "-3:", "-3:-1", ":-4:-1", "0,-2", "1::2", "-4::2", "2:-1",
+        ] {
+            let indices = indices(selection);
+            let reach = indices.reach_from_end();
+            for len in 1..12 {
+                let (selected, _) = indices.resolve(len);
+                for idx in 0..len {
+                    if selected.contains(&idx) {
+                        // never ruled out while reading up to the final length
+                        assert!((idx + 1..=len).all(|n| indices.may_select(idx, n)));
+                    } else if len > idx + reach {
+                        assert!(!indices.may_select(idx, len), "`{}` at {}", selection, idx);
+                    }
+                }
+            }
+        }
+        assert_eq!(indices("-3:-1").reach_from_end(), 3);
+    }
+
+    #[test]
+    fn test_selects_in_order() {
+        let indices = parse_array_indexing_operation("1:-1").unwrap();
+        assert!(!indices.selects_in_order(0, 3));
+        assert!(indices.selects_in_order(1, 3));
+        assert!(!indices.selects_in_order(2, 3));
+        assert!(indices.selects_in_order(2, 4));
+        assert!(!parse_array_indexing_operation("-3:")
+            .unwrap()
+            .selects_in_order(0, 10));
+        assert!(!parse_array_indexing_operation("0,-1")
+            .unwrap()
+            .selects_in_order(0, 10));
+    }
+
+    #[test]
+    fn test_resolve_indices_extreme_steps() {
+        let slice = |step| IndexSelector::Slice {
+            start: Some(1),
+            end: None,
+            step,
+        };
+        assert_eq!(slice(i64::MAX).resolve(5), Ok(vec![1]));
+        assert_eq!(slice(i64::MIN).resolve(5), Ok(vec![1]));
+    }
+
+    #[test]
+    fn test_resolve_indices_out_of_range() {
+        let resolve = |s: &str| parse_array_indexing_operation(s).unwrap().resolve(5);
+        assert_eq!(resolve("1,7,3"), (vec![1, 3], vec![7]));
+        assert_eq!(resolve("5"), (vec![], vec![5]));
+        assert_eq!(resolve("-6,-1"), (vec![4], vec![-6]));
     }
 }
@@ -0,0 +1,487 @@
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, ScanError, TScalarStyle};
+
+/// Byte range of a node in the source text it was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Mirror of a loaded `Yaml` document that records where each node lives in the source. Map
+/// entries and sequence items are in source order, so they line up with the loaded `Hash` and
+/// `Array` as long as the document has no duplicate keys.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpanNode {
+    /// An empty scalar (e.g. the value in `a:`) has an empty span.
+    Scalar {
+        span: Span,
+        style: TScalarStyle,
+    },
+    Alias {
+        span: Span,
+    },
+    Map {
+        span: Span,
+        flow: bool,
+        entries: Vec<(SpanNode, SpanNode)>,
+    },
+    Seq {
+        span: Span,
+        flow: bool,
+        items: Vec<SpanNode>,
+    },
+}
+
+impl SpanNode {
+    pub fn span(&self) -> Span {
+        match self {
+            SpanNode::Scalar { span, .. }
+            | SpanNode::Alias { span }
+            | SpanNode::Map { span, .. }
+            | SpanNode::Seq { span, .. } => *span,
+        }
+    }
+}
+
+struct EventCollector {
+    events: Vec<(Event, Marker)>,
+}
+
+impl MarkedEventReceiver for EventCollector {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        self.events.push((ev, mark));
+    }
+}
+
+/// Parses `source` and returns the span tree of each document in it.
+pub fn load_spans(source: &str) -> Result<Vec<SpanNode>, ScanError> {
+    let mut collector = EventCollector { events: Vec::new() };
+    Parser::new(source.chars()).load(&mut collector, true)?;
+    let mut builder = SpanBuilder {
+        source,
+        char_offsets: if source.is_ascii() {
+            None
+        } else {
+            Some(source.char_indices().map(|(idx, _)| idx).collect())
+        },
+        events: collector.events,
+        pos: 0,
+    };
+    let mut docs = Vec::new();
+    while builder.pos < builder.events.len() {
+        if let Event::DocumentStart = builder.events[builder.pos].0 {
+            builder.pos += 1;
+            docs.push(builder.build_node(-1, false));
+        } else {
+            builder.pos += 1;
+        }
+    }
+    Ok(docs)
+}
+
+struct SpanBuilder<'s> {
+    source: &'s str,
+    // byte offset of every char, only needed when the source is not ascii
+    char_offsets: Option<Vec<usize>>,
+    events: Vec<(Event, Marker)>,
+    pos: usize,
+}
+
+impl<'s> SpanBuilder<'s> {
+    fn byte_offset(&self, mark: &Marker) -> usize {
+        match &self.char_offsets {
+            Some(offsets) => offsets
+                .get(mark.index())
+                .copied()
+                .unwrap_or(self.source.len()),
+            None => mark.index(),
+        }
+    }
+
+    fn build_node(&mut self, parent_indent: isize, in_flow: bool) -> SpanNode {
+        let (event, mark) = self.events[self.pos].clone();
+        self.pos += 1;
+        let mut start = self.byte_offset(&mark);
+        match event {
+            Event::Scalar(value, style, ..) => {
+                if let TScalarStyle::Literal | TScalarStyle::Foled = style {
+                    // block scalars are reported at their first content line, not their header
+                    start = block_scalar_header(self.source, start);
+                } else if style == TScalarStyle::Plain
+                    && value == "~"
+                    && !self.source[start..].starts_with('~')
+                {
+                    // empty scalars are reported at the next token, so pin them to the preceding
+                    // indicator instead
+                    start = empty_scalar_pos(self.source, start);
+                    return SpanNode::Scalar {
+                        span: Span { start, end: start },
+                        style,
+                    };
+                }
+                let end = self.scalar_end(start, style, parent_indent, in_flow);
+                SpanNode::Scalar {
+                    span: Span { start, end },
+                    style,
+                }
+            }
+            Event::Alias(_) => {
+                let end = start
+                    + 1
+                    + self.source[start + 1..]
+                        .find(|c: char| c.is_whitespace() || ",[]{}".contains(c))
+                        .unwrap_or_else(|| self.source.len() - start - 1);
+                SpanNode::Alias {
+                    span: Span { start, end },
+                }
+            }
+            Event::SequenceStart(_) => {
+                let flow = self.source[start..].starts_with('[');
+                let indent = column(self.source, start) as isize;
+                let mut items = Vec::new();
+                while !self.at(|ev| matches!(ev, Event::SequenceEnd)) {
+                    items.push(self.build_node(indent, in_flow || flow));
+                }
+                let end = self.collection_end(flow, items.last().map(SpanNode::span), start);
+                SpanNode::Seq {
+                    span: Span { start, end },
+                    flow,
+                    items,
+                }
+            }
+            Event::MappingStart(_) => {
+                let flow = self.source[start..].starts_with('{');
+                if !flow {
+                    // block mappings are reported just past their first key, so start at the key
+                    start = self
+                        .events
+                        .get(self.pos)
+                        .map_or(start, |(_, key_mark)| self.byte_offset(key_mark));
+                }
+                let indent = column(self.source, start) as isize;
+                let mut entries = Vec::new();
+                while !self.at(|ev| matches!(ev, Event::MappingEnd)) {
+                    let key = self.build_node(indent, in_flow || flow);
+                    let value = self.build_node(indent, in_flow || flow);
+                    entries.push((key, value));
+                }
+                let end =
+                    self.collection_end(flow, entries.last().map(|(_, value)| value.span()), start);
+                SpanNode::Map {
+                    span: Span { start, end },
+                    flow,
+                    entries,
+                }
+            }
+            // the parser only hands out node events here
+            _ => SpanNode::Scalar {
+                span: Span { start, end: start },
+                style: TScalarStyle::Plain,
+            },
+        }
+    }
+
+    fn at<F: Fn(&Event) -> bool>(&self, is_event: F) -> bool {
+        self.pos >= self.events.len() || is_event(&self.events[self.pos].0)
+    }
+
+    /// Consumes the end event of a collection. Flow collections end after their closing bracket,
+    /// block collections end with their last child.
+    fn collection_end(&mut self, flow: bool, last_child: Option<Span>, start: usize) -> usize {
+        let end_mark = self.events.get(self.pos).map(|(_, mark)| *mark);
+        self.pos += 1;
+        match (flow, end_mark, last_child) {
+            (true, Some(mark), _) => self.byte_offset(&mark) + 1,
+            (false, _, Some(last_child)) => last_child.end,
+            _ => start,
+        }
+    }
+
+    fn scalar_end(
+        &self,
+        start: usize,
+        style: TScalarStyle,
+        parent_indent: isize,
+        in_flow: bool,
+    ) -> usize {
+        let bytes = self.source.as_bytes();
+        match style {
+            TScalarStyle::SingleQuoted => {
+                let mut idx = start + 1;
+                while idx < bytes.len() {
+                    if bytes[idx] == b'\'' {
+                        if bytes.get(idx + 1) != Some(&b'\'') {
+                            return idx + 1;
+                        }
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+                bytes.len()
+            }
+            TScalarStyle::DoubleQuoted => {
+                let mut idx = start + 1;
+                while idx < bytes.len() {
+                    match bytes[idx] {
+                        b'\\' => idx += 1,
+                        b'"' => return idx + 1,
+                        _ => {}
+                    }
+                    idx += 1;
+                }
+                bytes.len()
+            }
+            TScalarStyle::Literal | TScalarStyle::Foled => {
+                // the header line, then every blank or more indented line
+                let header_end = line_end(self.source, start);
+                let mut end = start + self.source[start..header_end].trim_end_matches('\r').len();
+                let mut line_start = header_end + 1;
+                while line_start < bytes.len() {
+                    let line = &self.source[line_start..line_end(self.source, line_start)];
+                    if !line.trim().is_empty() {
+                        if indentation(line) as isize <= parent_indent || is_doc_marker(line) {
+                            break;
+                        }
+                        end = line_start + line.trim_end_matches('\r').len();
+                    }
+                    line_start += line.len() + 1;
+                }
+                end
+            }
+            _ => {
+                let (mut end, mut continues) = plain_line_end(self.source, start, in_flow);
+                let mut line_start = line_end(self.source, end) + 1;
+                while continues && !in_flow && line_start < bytes.len() {
+                    let line = &self.source[line_start..line_end(self.source, line_start)];
+                    let trimmed = line.trim();
+                    if !trimmed.is_empty() {
+                        if indentation(line) as isize <= parent_indent
+                            || trimmed.starts_with('#')
+                            || is_doc_marker(line)
+                        {
+                            break;
+                        }
+                        let (next_end, next_continues) =
+                            plain_line_end(self.source, line_start + indentation(line), in_flow);
+                        end = next_end;
+                        continues = next_continues;
+                    }
+                    line_start += line.len() + 1;
+                }
+                end
+            }
+        }
+    }
+}
+
+/// Returns where a plain scalar ends on the line it starts on, and whether it may continue on
+/// the next line (it may not once it is followed by a `:` indicator or a comment).
+fn plain_line_end(source: &str, start: usize, in_flow: bool) -> (usize, bool) {
+    let bytes = source.as_bytes();
+    let mut idx = start;
+    let mut continues = true;
+    while idx < bytes.len() {
+        let c = bytes[idx];
+        let next = bytes.get(idx + 1).copied();
+        if c == b'\n' || c == b'\r' {
+            break;
+        }
+        if c == b'#' && idx > start && (bytes[idx - 1] == b' ' || bytes[idx - 1] == b'\t') {
+            continues = false;
+            break;
+        }
+        if c == b':'
+            && (next.is_none()
+                || next == Some(b' ')
+                || next == Some(b'\t')
+                || next == Some(b'\n')
+                || next == Some(b'\r')
+                || (in_flow && next.map_or(false, |n| b",[]{}".contains(&n))))
+        {
+            continues = false;
+            break;
+        }
+        if in_flow && b",[]{}".contains(&c) {
+            continues = false;
+            break;
+        }
+        idx += 1;
+    }
+    (start + source[start..idx].trim_end().len(), continues)
+}
+
+fn line_end(source: &str, pos: usize) -> usize {
+    source[pos..]
+        .find('\n')
+        .map_or(source.len(), |offset| pos + offset)
+}
+
+/// Returns the position just past the last indicator or token before `mark`, skipping blank and
+/// comment lines.
+pub fn empty_scalar_pos(source: &str, mark: usize) -> usize {
+    let mut pos = mark;
+    loop {
+        let start = line_start(source, pos);
+        let line = &source[start..pos];
+        let content = strip_comment(line).trim_end();
+        if !content.is_empty() {
+            return start + content.len();
+        }
+        if start == 0 {
+            return mark;
+        }
+        pos = start - 1;
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    if line.trim_start().starts_with('#') {
+        return "";
+    }
+    line.find(" #")
+        .map_or(line, |comment_idx| &line[..comment_idx])
+}
+
+/// Finds the `|` or `>` header of the block scalar whose content starts at `content_start`.
+pub fn block_scalar_header(source: &str, content_start: usize) -> usize {
+    let mut header_line_start = line_start(source, content_start);
+    while header_line_start > 0 {
+        header_line_start = line_start(source, header_line_start - 1);
+        let line = &source[header_line_start..line_end(source, header_line_start)];
+        if let Some(header_idx) = strip_comment(line).rfind(['|', '>']) {
+            return header_line_start + header_idx;
+        }
+    }
+    content_start
+}
+
+/// Splits a yaml stream into chunks that load on their own, starting one at every `---` line so
+/// that the documents of a stream can be loaded separately. Directives just above a `---` belong
+/// to the document it starts; anything before it that is no document, like leading comments,
+/// stays with the chunk before.
+pub fn split_documents(source: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut chunk_start = 0;
+    let mut directives_start = None;
+    let mut pos = 0;
+    while pos < source.len() {
+        let line = &source[pos..line_end(source, pos)];
+        if line.starts_with('%') {
+            directives_start.get_or_insert(pos);
+        } else if starts_document(line) {
+            let start = directives_start.take().unwrap_or(pos);
+            if start > chunk_start {
+                chunks.push(&source[chunk_start..start]);
+                chunk_start = start;
+            }
+        } else if !line.starts_with('#') {
+            directives_start = None;
+        }
+        pos = next_line_start(source, pos);
+    }
+    chunks.push(&source[chunk_start..]);
+    chunks
+}
+
+fn starts_document(line: &str) -> bool {
+    line.strip_prefix("---").map_or(false, |rest| {
+        rest.is_empty() || rest.starts_with([' ', '\t', '\r'])
+    })
+}
+
+/// Byte offset of the start of the line containing `pos`.
+pub fn line_start(source: &str, pos: usize) -> usize {
+    source[..pos].rfind('\n').map_or(0, |idx| idx + 1)
+}
+
+/// Byte offset just past the newline ending the line containing `pos`.
+pub fn next_line_start(source: &str, pos: usize) -> usize {
+    (line_end(source, pos) + 1).min(source.len())
+}
+
+pub fn column(source: &str, pos: usize) -> usize {
+    pos - line_start(source, pos)
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_doc_marker(line: &str) -> bool {
+    line.starts_with("---") || line.starts_with("...")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text<'s>(source: &'s str, node: &SpanNode) -> &'s str {
+        let span = node.span();
+        &source[span.start..span.end]
+    }
+
+    #[test]
+    fn test_load_spans_block_map() {
+        let source = "a: 1 # one\nb:\n  c: 'x'\n  d: \"y\\\"\"\ne:\n";
+        let docs = load_spans(source).unwrap();
+        assert_eq!(docs.len(), 1);
+        let entries = match &docs[0] {
+            SpanNode::Map { entries, flow, .. } => {
+                assert!(!flow);
+                entries
+            }
+            _ => panic!("invalid span node, not a map"),
+        };
+        assert_eq!(text(source, &entries[0].0), "a");
+        assert_eq!(text(source, &entries[0].1), "1");
+        assert_eq!(text(source, &entries[1].1), "c: 'x'\n  d: \"y\\\"\"");
+        assert!(entries[2].1.span().is_empty());
+        assert_eq!(entries[2].1.span().start, source.len() - 1);
+        assert_eq!(text(source, &docs[0]), &source[..source.len() - 1]);
+    }
+
+    #[test]
+    fn test_load_spans_seqs() {
+        let source = "- [a, b]\n- plain\n  continued\n- |\n  literal\n\n- {x: y}\n";
+        let docs = load_spans(source).unwrap();
+        let items = match &docs[0] {
+            SpanNode::Seq { items, .. } => items,
+            _ => panic!("invalid span node, not a seq"),
+        };
+        assert_eq!(text(source, &items[0]), "[a, b]");
+        assert_eq!(text(source, &items[1]), "plain\n  continued");
+        assert_eq!(text(source, &items[2]), "|\n  literal");
+        assert_eq!(text(source, &items[3]), "{x: y}");
+    }
+
+    #[test]
+    fn test_load_spans_multi_doc_non_ascii() {
+        let source = "a: é\n---\nb: ü # c\n";
+        let docs = load_spans(source).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(text(source, &docs[0]), "a: é");
+        assert_eq!(text(source, &docs[1]), "b: ü");
+    }
+
+    #[test]
+    fn test_split_documents() {
+        let source = "# top\na: 1\n--- # one\nb: |\n  ---\n%YAML 1.2\n---\nc\n---x\n...\n";
+        assert_eq!(
+            split_documents(source),
+            vec![
+                "# top\na: 1\n",
+                "--- # one\nb: |\n  ---\n",
+                "%YAML 1.2\n---\nc\n---x\n...\n"
+            ]
+        );
+        assert_eq!(split_documents("---\na\n---"), vec!["---\na\n", "---"]);
+        assert_eq!(split_documents(""), vec![""]);
+    }
+}
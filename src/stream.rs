@@ -0,0 +1,1145 @@
+use crate::error::Error;
+use crate::marker::{MarkTree, Marker, SourceMap, SourceWindow, WindowedChars};
+use crate::path::{is_child_filter_match, ArrayIndices, PathSegment};
+use crate::spans::{block_scalar_header, empty_scalar_pos};
+use crate::traverse::{
+    extend_array_path, extend_hash_path, hash_entry_matches, hash_key_str, traverse_node,
+    traverse_with, TraverseOptions, VisitedNode,
+};
+use log::debug;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use yaml_rust::parser::{Event, Parser};
+use yaml_rust::scanner::{self, TScalarStyle, TokenType};
+use yaml_rust::Yaml;
+
+/// A node matched by `stream_search`. Unlike `VisitedNode` it owns the matched subtree, as the
+/// rest of the document is never built.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamedNode {
+    pub yml: Yaml,
+    pub path: String,
+    pub marker: Option<Marker>,
+}
+
+/// What `stream_search` reports to its callback, in input order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    DocumentStart(usize),
+    Match(StreamedNode),
+    DocumentEnd(usize),
+}
+
+/// Searches a stream of yaml documents for `path` straight from parser events, without loading
+/// whole documents. Only matched subtrees, the entries or elements a child filter has to look
+/// at, and anchored nodes are built; everything else is skipped as it is parsed. Matches are
+/// reported in the same order as `traverse` would report them, as soon as that order allows.
+///
+/// Only the document at `doc_idx` is searched if it is given. `options` work like they do for
+/// `traverse_with`, with `options.limit` counting matches across all documents: once it is
+/// reached the current document ends and the rest of the stream is not read. Returns the number
+/// of documents read.
+pub fn stream_search<I, F>(
+    source: I,
+    path: &[PathSegment],
+    doc_idx: Option<usize>,
+    options: TraverseOptions,
+    on_event: F,
+) -> Result<usize, Error>
+where
+    I: Iterator<Item = char>,
+    F: FnMut(StreamEvent) -> Result<(), Error>,
+{
+    run_engine(source, path, doc_idx, options, false, on_event)
+}
+
+/// Counts the matches of `path` in a stream of yaml documents like `stream_search` finds them,
+/// calling `on_count` with the index and number of matches of each document searched. Matched
+/// nodes are never built, only counted. Returns the number of documents read.
+pub fn stream_count<I, F>(
+    source: I,
+    path: &[PathSegment],
+    doc_idx: Option<usize>,
+    options: TraverseOptions,
+    mut on_count: F,
+) -> Result<usize, Error>
+where
+    I: Iterator<Item = char>,
+    F: FnMut(usize, usize) -> Result<(), Error>,
+{
+    let mut matched = 0;
+    let on_event = |event| match event {
+        StreamEvent::DocumentStart(_) => {
+            matched = 0;
+            Ok(())
+        }
+        StreamEvent::Match(_) => {
+            matched += 1;
+            Ok(())
+        }
+        StreamEvent::DocumentEnd(idx) => on_count(idx, matched),
+    };
+    run_engine(source, path, doc_idx, options, true, on_event)
+}
+
+fn run_engine<I, F>(
+    source: I,
+    path: &[PathSegment],
+    doc_idx: Option<usize>,
+    options: TraverseOptions,
+    count_only: bool,
+    on_event: F,
+) -> Result<usize, Error>
+where
+    I: Iterator<Item = char>,
+    F: FnMut(StreamEvent) -> Result<(), Error>,
+{
+    let window = Rc::new(RefCell::new(SourceWindow::new()));
+    let mut engine = StreamEngine {
+        parser: Parser::new(WindowedChars {
+            chars: source,
+            window: Rc::clone(&window),
+        }),
+        window,
+        last_mark: 0,
+        path,
+        options,
+        count_only,
+        matched: 0,
+        anchors: HashMap::new(),
+        bufs: Vec::new(),
+        on_event,
+    };
+    engine.run(doc_idx)
+}
+
+// destination for matches that can be reported right away
+const SINK: usize = usize::MAX;
+
+/// A pending call of `traverse` (for the document root) or `traverse_node` on the next node.
+#[derive(Debug, Clone, Copy)]
+enum Invocation {
+    Root,
+    Node { head: usize, following_splat: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Visit,
+    Recurse { head: usize, following_splat: bool },
+    Nothing,
+}
+
+/// How a recursing path segment selects array elements.
+enum ArraySelection<'p> {
+    All,
+    /// `ArrayIndices::Indices` or `ArrayIndices::Relative`
+    Indices(&'p ArrayIndices),
+    Filter,
+    No,
+}
+
+struct StreamEngine<'p, I: Iterator<Item = char>, F> {
+    parser: Parser<WindowedChars<I>>,
+    // the source read since the last event, for working out where nodes start
+    window: Rc<RefCell<SourceWindow>>,
+    last_mark: usize,
+    path: &'p [PathSegment],
+    options: TraverseOptions,
+    // matches are only counted, so they are reported without being built
+    count_only: bool,
+    // matches reported so far
+    matched: usize,
+    anchors: HashMap<usize, Yaml>,
+    // buffered matches that can't be reported until a node is done, used as a stack
+    bufs: Vec<Vec<StreamedNode>>,
+    on_event: F,
+}
+
+impl<'p, I, F> StreamEngine<'p, I, F>
+where
+    I: Iterator<Item = char>,
+    F: FnMut(StreamEvent) -> Result<(), Error>,
+{
+    fn run(&mut self, doc_idx: Option<usize>) -> Result<usize, Error> {
+        let mut num_docs = 0;
+        loop {
+            match self.next_event()?.0 {
+                Event::StreamEnd => return Ok(num_docs),
+                Event::DocumentStart => {
+                    let idx = num_docs;
+                    num_docs += 1;
+                    if doc_idx.map_or(true, |doc_idx| doc_idx == idx) {
+                        debug!("streaming document at index `{}`", idx);
+                        (self.on_event)(StreamEvent::DocumentStart(idx))?;
+                        self.stream_node(vec![(Invocation::Root, SINK)], String::new())?;
+                        (self.on_event)(StreamEvent::DocumentEnd(idx))?;
+                        if self.is_done() {
+                            debug!("reached the limit of matches, stopping");
+                            return Ok(num_docs);
+                        }
+                    } else {
+                        self.skip_node()?;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn next_event(&mut self) -> Result<(Event, scanner::Marker), Error> {
+        let (event, mark) = self.parser.next()?;
+        // nodes never start before the event preceding them, so earlier lines aren't needed
+        self.window.borrow_mut().release(self.last_mark);
+        self.last_mark = mark.index();
+        Ok((event, mark))
+    }
+
+    /// Works out where the node of `event` starts, as the parser doesn't report block mappings,
+    /// block scalars and empty scalars at their start. Must be called before the next event is
+    /// taken.
+    fn node_marker(&mut self, event: &Event, mark: scanner::Marker) -> Result<Marker, Error> {
+        let first_key_mark = match event {
+            Event::MappingStart(_) => Some(self.parser.peek()?.1),
+            _ => None,
+        };
+        let window = self.window.borrow();
+        let mut pos = window.offset_of(mark.index());
+        match event {
+            Event::Scalar(_, TScalarStyle::Literal, ..)
+            | Event::Scalar(_, TScalarStyle::Foled, ..) => {
+                pos = block_scalar_header(&window.text, pos);
+            }
+            Event::Scalar(value, TScalarStyle::Plain, ..)
+                if value == "~" && !window.text[pos..].starts_with('~') =>
+            {
+                pos = empty_scalar_pos(&window.text, pos);
+            }
+            Event::MappingStart(_) if !window.text[pos..].starts_with('{') => {
+                // block mappings are reported just past their first key
+                if let Some(key_mark) = first_key_mark {
+                    pos = window.offset_of(key_mark.index());
+                }
+            }
+            _ => {}
+        }
+        Ok(window.marker(pos))
+    }
+
+    fn peek_event(&mut self) -> Result<&Event, Error> {
+        Ok(&self.parser.peek()?.0)
+    }
+
+    /// Whether `options.limit` matches have been reported, so nothing else has to be read.
+    fn is_done(&self) -> bool {
+        self.options
+            .limit
+            .map_or(false, |limit| self.matched >= limit)
+    }
+
+    fn emit(&mut self, dest: usize, node: StreamedNode) -> Result<(), Error> {
+        if dest == SINK {
+            if self.is_done() {
+                return Ok(());
+            }
+            self.matched += 1;
+            (self.on_event)(StreamEvent::Match(node))
+        } else {
+            self.bufs[dest].push(node);
+            Ok(())
+        }
+    }
+
+    fn alloc_buf(&mut self) -> usize {
+        self.bufs.push(Vec::new());
+        self.bufs.len() - 1
+    }
+
+    fn flush_buf(&mut self, buf: usize, dest: usize) -> Result<(), Error> {
+        for node in std::mem::take(&mut self.bufs[buf]) {
+            self.emit(dest, node)?;
+        }
+        Ok(())
+    }
+
+    /// Mirrors the decisions `traverse` and `traverse_node` make for a node.
+    fn action(&self, invocation: Invocation, scalar: bool) -> Action {
+        match invocation {
+            Invocation::Root => {
+                if !self.path.is_empty() && !scalar {
+                    Action::Recurse {
+                        head: 0,
+                        following_splat: false,
+                    }
+                } else if self.path.is_empty() {
+                    Action::Visit
+                } else {
+                    Action::Nothing
+                }
+            }
+            Invocation::Node {
+                head,
+                following_splat,
+            } => {
+                let tail_is_empty = head + 1 == self.path.len();
+                if following_splat {
+                    if self.path[head].is_splat() {
+                        if !tail_is_empty {
+                            Action::Recurse {
+                                head: head + 1,
+                                following_splat: true,
+                            }
+                        } else if scalar {
+                            Action::Visit
+                        } else {
+                            Action::Recurse {
+                                head,
+                                following_splat: false,
+                            }
+                        }
+                    } else if !scalar {
+                        Action::Recurse {
+                            head,
+                            following_splat: true,
+                        }
+                    } else {
+                        Action::Nothing
+                    }
+                } else if !tail_is_empty && !scalar {
+                    Action::Recurse {
+                        head: head + 1,
+                        following_splat: false,
+                    }
+                } else if tail_is_empty {
+                    Action::Visit
+                } else {
+                    Action::Nothing
+                }
+            }
+        }
+    }
+
+    /// Runs each invocation against the next node, sending its matches to its destination.
+    fn stream_node(
+        &mut self,
+        invocations: Vec<(Invocation, usize)>,
+        path: String,
+    ) -> Result<(), Error> {
+        if invocations.is_empty() {
+            return self.skip_node();
+        }
+        let (scalar, anchored) = match self.peek_event()? {
+            Event::Scalar(_, _, anchor_id, _) => (true, *anchor_id > 0),
+            Event::SequenceStart(anchor_id) | Event::MappingStart(anchor_id) => {
+                (false, *anchor_id > 0)
+            }
+            // aliases are resolved to a copy of their anchored node, like `YamlLoader` does
+            _ => {
+                let (node, tree) = self.load_node()?;
+                return self.evaluate(&node, &tree, &invocations, &path);
+            }
+        };
+
+        let actions: Vec<(Action, usize)> = invocations
+            .iter()
+            .map(|(invocation, dest)| (self.action(*invocation, scalar), *dest))
+            .collect();
+        let visits = actions.iter().any(|(action, _)| *action == Action::Visit);
+        if anchored || (visits && !self.count_only) {
+            let (node, tree) = self.load_node()?;
+            return self.evaluate(&node, &tree, &invocations, &path);
+        }
+        if visits {
+            // when counting, the order matches are reported in doesn't matter
+            for (action, dest) in &actions {
+                if *action == Action::Visit {
+                    self.emit(*dest, counted_node())?;
+                }
+            }
+        }
+
+        // only the first invocation reporting to a destination can report to it directly,
+        // the others have to wait until it is done with this node
+        let mark = self.bufs.len();
+        let mut deferred = Vec::new();
+        let mut recursions = Vec::new();
+        for (action, dest) in actions {
+            if let Action::Recurse {
+                head,
+                following_splat,
+            } = action
+            {
+                let mut dest = dest;
+                if recursions.iter().any(|(_, _, d)| *d == dest)
+                    || deferred.iter().any(|(_, d)| *d == dest)
+                {
+                    let buf = self.alloc_buf();
+                    deferred.push((buf, dest));
+                    dest = buf;
+                }
+                recursions.push((head, following_splat, dest));
+            }
+        }
+
+        match self.peek_event()? {
+            Event::MappingStart(_) if !recursions.is_empty() => {
+                self.stream_hash(&recursions, &path)?
+            }
+            Event::SequenceStart(_) if !recursions.is_empty() => {
+                self.stream_array(&recursions, &path)?
+            }
+            _ => self.skip_node()?,
+        }
+
+        for (buf, dest) in deferred {
+            self.flush_buf(buf, dest)?;
+        }
+        self.bufs.truncate(mark);
+        Ok(())
+    }
+
+    /// Streams the entries of a map, like `recurse_hash` does for each recursion.
+    fn stream_hash(
+        &mut self,
+        recursions: &[(usize, bool, usize)],
+        path: &str,
+    ) -> Result<(), Error> {
+        self.next_event()?;
+        // matching a child filter against an entry needs the entry's value
+        let needs_values = recursions
+            .iter()
+            .any(|(head, _, _)| matches!(self.path[*head], PathSegment::ChildFilter(_)));
+        loop {
+            if let Event::MappingEnd = self.peek_event()? {
+                self.next_event()?;
+                return Ok(());
+            }
+            let (key, _) = self.load_node()?;
+            let k_str = hash_key_str(&key)?;
+            let child_path = extend_hash_path(path, &k_str);
+            let value = if needs_values {
+                Some(self.load_node()?)
+            } else {
+                None
+            };
+
+            let mut child_invocations = Vec::new();
+            for (head, following_splat, dest) in recursions {
+                let segment = &self.path[*head];
+                if *following_splat {
+                    child_invocations.push((
+                        Invocation::Node {
+                            head: *head,
+                            following_splat: true,
+                        },
+                        *dest,
+                    ));
+                }
+                let v = value.as_ref().map_or(&Yaml::Null, |(v, _)| v);
+                if hash_entry_matches(&k_str, v, segment, &self.path[head + 1..])? {
+                    child_invocations.push((
+                        Invocation::Node {
+                            head: *head,
+                            following_splat: segment.is_splat(),
+                        },
+                        *dest,
+                    ));
+                }
+            }
+            match value {
+                Some((value, tree)) => {
+                    self.evaluate(&value, &tree, &child_invocations, &child_path)?
+                }
+                None => self.stream_node(child_invocations, child_path)?,
+            }
+            // the rest of the map is never read
+            if self.is_done() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Streams the elements of an array, like `recurse_array` does for each recursion: matches
+    /// found following a splat come first, then those of the selected indices.
+    fn stream_array(
+        &mut self,
+        recursions: &[(usize, bool, usize)],
+        path: &str,
+    ) -> Result<(), Error> {
+        self.next_event()?;
+        let path_segments = self.path;
+        let mark = self.bufs.len();
+
+        let mut selections = Vec::with_capacity(recursions.len());
+        let mut index_dests = Vec::with_capacity(recursions.len());
+        let mut index_bufs: Vec<BTreeMap<usize, usize>> = Vec::with_capacity(recursions.len());
+        // buffers of elements a relative selection turned out not to select, for reuse
+        let mut free_bufs = Vec::new();
+        for (head, following_splat, dest) in recursions {
+            let selection = match &path_segments[*head] {
+                PathSegment::Splat | PathSegment::Index(ArrayIndices::Star) => ArraySelection::All,
+                PathSegment::Index(indices) => ArraySelection::Indices(indices),
+                PathSegment::ChildFilter(_) => ArraySelection::Filter,
+                _ => ArraySelection::No,
+            };
+            // matches of selected elements have to wait for the splat matches of all elements
+            let index_dest = if *following_splat {
+                self.alloc_buf()
+            } else {
+                *dest
+            };
+            let mut bufs = BTreeMap::new();
+            if let ArraySelection::Indices(ArrayIndices::Indices(indices)) = selection {
+                // indices may be listed in any order, so matches always wait for the array's end
+                for idx in indices {
+                    if !bufs.contains_key(idx) {
+                        let buf = self.alloc_buf();
+                        bufs.insert(*idx, buf);
+                    }
+                }
+            }
+            selections.push(selection);
+            index_dests.push(index_dest);
+            index_bufs.push(bufs);
+        }
+        let needs_elements = selections
+            .iter()
+            .any(|selection| matches!(selection, ArraySelection::Filter));
+
+        let mut len = 0;
+        loop {
+            if let Event::SequenceEnd = self.peek_event()? {
+                self.next_event()?;
+                break;
+            }
+            let child_path = extend_array_path(path, len);
+            let element = if needs_elements {
+                Some(self.load_node()?)
+            } else {
+                None
+            };
+
+            let mut child_invocations = Vec::new();
+            for (r, (head, following_splat, dest)) in recursions.iter().enumerate() {
+                let segment = &path_segments[*head];
+                if *following_splat {
+                    child_invocations.push((
+                        Invocation::Node {
+                            head: *head,
+                            following_splat: true,
+                        },
+                        *dest,
+                    ));
+                }
+                let selected_dest = match &selections[r] {
+                    ArraySelection::All => Some(index_dests[r]),
+                    ArraySelection::Indices(ArrayIndices::Indices(_)) => {
+                        index_bufs[r].get(&len).copied()
+                    }
+                    ArraySelection::Indices(indices) => {
+                        // which elements are selected is only known from the array's length, so
+                        // the matches of every element that may be are kept until then
+                        if indices.may_select(len, len + 1) {
+                            let buf = free_bufs.pop().unwrap_or_else(|| self.alloc_buf());
+                            index_bufs[r].insert(len, buf);
+                            Some(buf)
+                        } else {
+                            None
+                        }
+                    }
+                    ArraySelection::Filter => match (segment, &element) {
+                        (PathSegment::ChildFilter(filter), Some((element, _))) => {
+                            let is_final = head + 1 == path_segments.len();
+                            if is_child_filter_match(element, filter, is_final)? {
+                                Some(index_dests[r])
+                            } else {
+                                None
+                            }
+                        }
+                        _ => None,
+                    },
+                    ArraySelection::No => None,
+                };
+                if let Some(selected_dest) = selected_dest {
+                    child_invocations.push((
+                        Invocation::Node {
+                            head: *head,
+                            following_splat: segment.is_splat(),
+                        },
+                        selected_dest,
+                    ));
+                }
+            }
+            match element {
+                Some((element, tree)) => {
+                    self.evaluate(&element, &tree, &child_invocations, &child_path)?
+                }
+                None => self.stream_node(child_invocations, child_path)?,
+            }
+            // the rest of the array is never read, and neither are the matches still buffered
+            if self.is_done() {
+                self.bufs.truncate(mark);
+                return Ok(());
+            }
+            len += 1;
+            for (r, selection) in selections.iter().enumerate() {
+                if let ArraySelection::Indices(indices @ ArrayIndices::Relative(_)) = selection {
+                    self.settle_relative_bufs(
+                        indices,
+                        len,
+                        &mut index_bufs[r],
+                        index_dests[r],
+                        &mut free_bufs,
+                    )?;
+                }
+            }
+        }
+
+        for (r, (_, _, dest)) in recursions.iter().enumerate() {
+            if let ArraySelection::Indices(indices) = selections[r] {
+                let (indices, out_of_range) = indices.resolve(len);
+                if let Some(idx) = out_of_range.first() {
+                    if self.options.strict {
+                        return Err(Error::IndexOutOfRange {
+                            path: format!("{}[{}]", path, idx),
+                            len,
+                        });
+                    }
+                    debug!("skipping array indices out of range: {:?}", out_of_range);
+                }
+                for idx in indices {
+                    // elements reported early are no longer buffered
+                    if let Some(buf) = index_bufs[r].get(&idx) {
+                        for node in self.bufs[*buf].clone() {
+                            self.emit(index_dests[r], node)?;
+                        }
+                    }
+                }
+            }
+            if index_dests[r] != *dest {
+                self.flush_buf(index_dests[r], *dest)?;
+            }
+        }
+        self.bufs.truncate(mark);
+        Ok(())
+    }
+
+    /// Once `len` elements of an array have been read, frees the buffer of the element that has
+    /// just moved out of reach of the negative indices of a relative selection if it turned out
+    /// not to be selected, so only the last few elements are ever kept for `[-1]` or `[-3:]`.
+    /// Matches of elements a single slice is sure to select are reported straight away.
+    fn settle_relative_bufs(
+        &mut self,
+        indices: &ArrayIndices,
+        len: usize,
+        bufs: &mut BTreeMap<usize, usize>,
+        dest: usize,
+        free_bufs: &mut Vec<usize>,
+    ) -> Result<(), Error> {
+        if let Some(idx) = len.checked_sub(indices.reach_from_end().saturating_add(1)) {
+            if !indices.may_select(idx, len) {
+                if let Some(buf) = bufs.remove(&idx) {
+                    self.bufs[buf].clear();
+                    free_bufs.push(buf);
+                }
+            }
+        }
+        while let Some((&idx, &buf)) = bufs.iter().next() {
+            if !indices.selects_in_order(idx, len) {
+                break;
+            }
+            bufs.remove(&idx);
+            self.flush_buf(buf, dest)?;
+            free_bufs.push(buf);
+        }
+        Ok(())
+    }
+
+    /// Runs invocations against a node that has already been built, using `traverse` itself.
+    fn evaluate(
+        &mut self,
+        node: &Yaml,
+        tree: &MarkTree,
+        invocations: &[(Invocation, usize)],
+        path: &str,
+    ) -> Result<(), Error> {
+        let source_map = if self.count_only {
+            None
+        } else {
+            Some(SourceMap::from_tree(node, tree))
+        };
+        for (invocation, dest) in invocations {
+            if self.is_done() {
+                break;
+            }
+            // no more than the matches still missing can ever be reported from one invocation
+            let options = TraverseOptions {
+                limit: self.options.limit.map(|limit| limit - self.matched),
+                ..self.options
+            };
+            let mut visited = Vec::<VisitedNode>::new();
+            match invocation {
+                Invocation::Root => traverse_with(node, self.path, options, &mut visited)?,
+                Invocation::Node {
+                    head,
+                    following_splat,
+                } => traverse_node(
+                    node,
+                    &self.path[*head],
+                    &self.path[head + 1..],
+                    path.to_string(),
+                    *following_splat,
+                    options,
+                    &mut visited,
+                )?,
+            }
+            for visited_node in visited {
+                let node = match &source_map {
+                    Some(source_map) => StreamedNode {
+                        yml: visited_node.yml.clone(),
+                        path: visited_node.path,
+                        marker: source_map.marker(visited_node.yml),
+                    },
+                    None => counted_node(),
+                };
+                self.emit(*dest, node)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the next node, the same way `YamlLoader` would, along with the markers of it and
+    /// its children.
+    fn load_node(&mut self) -> Result<(Yaml, MarkTree), Error> {
+        let (event, mark) = self.next_event()?;
+        let marker = self.node_marker(&event, mark)?;
+        let (node, tree, anchor_id) = match event {
+            Event::Scalar(value, style, anchor_id, tag) => (
+                load_scalar(value, style, tag),
+                MarkTree::Node(marker),
+                anchor_id,
+            ),
+            Event::SequenceStart(anchor_id) => {
+                let mut array = Vec::new();
+                let mut items = Vec::new();
+                while !matches!(self.peek_event()?, Event::SequenceEnd) {
+                    let (item, item_tree) = self.load_node()?;
+                    array.push(item);
+                    items.push(item_tree);
+                }
+                self.next_event()?;
+                (Yaml::Array(array), MarkTree::Seq(marker, items), anchor_id)
+            }
+            Event::MappingStart(anchor_id) => {
+                let mut hash = yaml_rust::yaml::Hash::new();
+                let mut entries = Vec::new();
+                while !matches!(self.peek_event()?, Event::MappingEnd) {
+                    let (k, k_tree) = self.load_node()?;
+                    let (v, v_tree) = self.load_node()?;
+                    hash.insert(k, v);
+                    entries.push((k_tree, v_tree));
+                }
+                self.next_event()?;
+                (Yaml::Hash(hash), MarkTree::Map(marker, entries), anchor_id)
+            }
+            Event::Alias(anchor_id) => (
+                self.anchors
+                    .get(&anchor_id)
+                    .cloned()
+                    .unwrap_or(Yaml::BadValue),
+                MarkTree::Node(marker),
+                0,
+            ),
+            _ => (Yaml::BadValue, MarkTree::Node(marker), 0),
+        };
+        if anchor_id > 0 {
+            self.anchors.insert(anchor_id, node.clone());
+        }
+        Ok((node, tree))
+    }
+
+    /// Consumes the next node without building it. Anchored nodes are still built, as aliases
+    /// later in the stream may refer to them.
+    fn skip_node(&mut self) -> Result<(), Error> {
+        match self.peek_event()? {
+            Event::Scalar(_, _, anchor_id, _)
+            | Event::SequenceStart(anchor_id)
+            | Event::MappingStart(anchor_id)
+                if *anchor_id > 0 =>
+            {
+                self.load_node()?;
+                return Ok(());
+            }
+            _ => {}
+        }
+        match self.next_event()?.0 {
+            Event::SequenceStart(_) => {
+                while !matches!(self.peek_event()?, Event::SequenceEnd) {
+                    self.skip_node()?;
+                }
+                self.next_event()?;
+            }
+            Event::MappingStart(_) => {
+                while !matches!(self.peek_event()?, Event::MappingEnd) {
+                    self.skip_node()?;
+                    self.skip_node()?;
+                }
+                self.next_event()?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Stands in for a match that is only counted.
+fn counted_node() -> StreamedNode {
+    StreamedNode {
+        yml: Yaml::BadValue,
+        path: String::new(),
+        marker: None,
+    }
+}
+
+/// Converts a scalar event into a node, following `YamlLoader`.
+fn load_scalar(value: String, style: TScalarStyle, tag: Option<TokenType>) -> Yaml {
+    if style != TScalarStyle::Plain {
+        return Yaml::String(value);
+    }
+    match tag {
+        Some(TokenType::Tag(ref handle, ref suffix)) if handle == "!!" => match suffix.as_ref() {
+            "bool" => value.parse::<bool>().map_or(Yaml::BadValue, Yaml::Boolean),
+            "int" => value.parse::<i64>().map_or(Yaml::BadValue, Yaml::Integer),
+            "float" => match Yaml::from_str(&value) {
+                Yaml::Real(_) | Yaml::Integer(_) => Yaml::Real(value),
+                _ => Yaml::BadValue,
+            },
+            "null" => match value.as_ref() {
+                "~" | "null" => Yaml::Null,
+                _ => Yaml::BadValue,
+            },
+            _ => Yaml::String(value),
+        },
+        Some(TokenType::Tag(..)) => Yaml::String(value),
+        _ => Yaml::from_str(&value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::parse_path;
+    use crate::traverse::traverse_with;
+    use yaml_rust::YamlLoader;
+
+    fn stream_matches(source: &str, path: &str, limit: Option<usize>) -> Vec<StreamedNode> {
+        let mut matches = Vec::new();
+        stream_search(
+            source.chars(),
+            &parse_path(path).unwrap(),
+            None,
+            TraverseOptions {
+                limit,
+                ..TraverseOptions::default()
+            },
+            |event| {
+                if let StreamEvent::Match(node) = event {
+                    matches.push(node);
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+        matches
+    }
+
+    fn traverse_matches(source: &str, path: &str, limit: Option<usize>) -> Vec<StreamedNode> {
+        let docs = YamlLoader::load_from_str(source).unwrap();
+        let source_map = SourceMap::new(source, &docs).unwrap();
+        let mut matches = Vec::new();
+        for doc in &docs {
+            let mut visited = Vec::<VisitedNode>::new();
+            let options = TraverseOptions {
+                limit: limit.map(|limit| limit - matches.len()),
+                ..TraverseOptions::default()
+            };
+            traverse_with(doc, &parse_path(path).unwrap(), options, &mut visited).unwrap();
+            source_map.mark(&mut visited);
+            matches.extend(visited.into_iter().map(|v| StreamedNode {
+                yml: v.yml.clone(),
+                path: v.path,
+                marker: v.marker,
+            }));
+        }
+        matches
+    }
+
+    const DOC: &str = "
+a:
+  b:
+    - c: 1
+      d: crab
+    - c: 2
+      d:
+        c: 3
+    - c: 4
+  e: &anchor
+    c: 5
+    f: [6, 7]
+  g: *anchor
+  h: crabby
+  i:
+  j: |
+    block
+  k: \"two
+    lines\" # comment
+  l: {m: [é, {n: }], o: ~}
+list:
+  - x
+  - y
+  - z
+";
+
+    const PATHS: &[&str] = &[
+        "",
+        "a",
+        "a.b",
+        "a.b[1].c",
+        "a.b[*].c",
+        "a.b[5].c",
+        "a.*.c",
+        "a.h*",
+        "**",
+        "**.c",
+        "a.**.c",
+        "**.f[1]",
+        "a.b.(c==2).d",
+        "a.b.(d.c==3)",
+        "a.(.==crab*)",
+        "a.(c==5)",
+        "a.(c==5).f[0]",
+        "**.(c==[13])",
+        "a.(f[1]==7)",
+        "list(.==[yz])",
+        "list[2]",
+        "list[-1,0]",
+        "list[-9]",
+        "a.b[-1].c",
+        "a.b[::-1].c",
+        "**.f[-3:]",
+        "list.**",
+        "g.c",
+        "a.g.f",
+        "a.i",
+        "a.*",
+        "a.l.**",
+        "a.{b,g}.c",
+        "a.(f|h*)",
+        "**.{c,d}",
+    ];
+
+    #[test]
+    fn test_stream_search_matches_traverse() {
+        for path in PATHS {
+            assert_eq!(
+                stream_matches(DOC, path, None),
+                traverse_matches(DOC, path, None),
+                "path `{}`",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_search_limit_matches_traverse() {
+        for path in PATHS {
+            let all = traverse_matches(DOC, path, None);
+            for limit in 1..4 {
+                let first = &all[..all.len().min(limit)];
+                assert_eq!(
+                    traverse_matches(DOC, path, Some(limit)),
+                    first,
+                    "path `{}`, limit {}",
+                    path,
+                    limit
+                );
+                assert_eq!(
+                    stream_matches(DOC, path, Some(limit)),
+                    first,
+                    "path `{}`, limit {}",
+                    path,
+                    limit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_count_matches_traverse() {
+        let source = format!("{}---\n{}", DOC, DOC);
+        for path in PATHS {
+            let expected = traverse_matches(DOC, path, None).len();
+            let mut counts = Vec::new();
+            let num_docs = stream_count(
+                source.chars(),
+                &parse_path(path).unwrap(),
+                None,
+                TraverseOptions::default(),
+                |idx, count| {
+                    counts.push((idx, count));
+                    Ok(())
+                },
+            )
+            .unwrap();
+            assert_eq!(num_docs, 2);
+            assert_eq!(
+                counts,
+                vec![(0, expected), (1, expected)],
+                "path `{}`",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_search_relative_indices_match_traverse() {
+        let selections = [
+            "-1", "-3", "0,-1", "-1,-1", "-2:", ":-2", "1:-1", "::2", "1::3", "-4::2", "-2:1:-1",
+            ":-4:-1", "::-2", "2::-1", "-1:0:-1", "-3:-1,1",
+        ];
+        for len in 0..8 {
+            let items: Vec<String> = (0..len).map(|i| format!("{{v: {}}}", i)).collect();
+            let source = format!("a: [{}]\n", items.join(", "));
+            for selection in &selections {
+                let path = format!("a[{}].v", selection);
+                assert_eq!(
+                    stream_matches(&source, &path, None),
+                    traverse_matches(&source, &path, None),
+                    "path `{}`, length {}",
+                    path,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_search_reports_slices_before_array_end() {
+        let mut matches = Vec::new();
+        let result = stream_search(
+            "a: [1, 2, 3, [unterminated".chars(),
+            &parse_path("a[:-1]").unwrap(),
+            None,
+            TraverseOptions::default(),
+            |e| {
+                if let StreamEvent::Match(node) = e {
+                    matches.push(node.yml);
+                }
+                Ok(())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(matches, vec![Yaml::Integer(1), Yaml::Integer(2)]);
+    }
+
+    #[test]
+    fn test_stream_search_limit_stops_reading() {
+        let mut events = Vec::new();
+        let num_docs = stream_search(
+            "a: 1\n---\na: 2\n---\na: [unterminated".chars(),
+            &parse_path("a").unwrap(),
+            None,
+            TraverseOptions {
+                limit: Some(2),
+                ..TraverseOptions::default()
+            },
+            |e| {
+                events.push(e);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(num_docs, 2);
+        assert_eq!(events.len(), 6);
+    }
+
+    #[test]
+    fn test_stream_search_strict() {
+        let source = "a: [[1, 2], [3]]\n";
+        let search = |path: &str| {
+            stream_search(
+                source.chars(),
+                &parse_path(path).unwrap(),
+                None,
+                TraverseOptions {
+                    strict: true,
+                    ..TraverseOptions::default()
+                },
+                |_| Ok(()),
+            )
+        };
+        assert!(search("a[*][0]").is_ok());
+        match search("a[*][-2]") {
+            Err(Error::IndexOutOfRange { path, len }) => {
+                assert_eq!(path, "a[1][-2]");
+                assert_eq!(len, 1);
+            }
+            other => panic!("expected an out of range error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stream_search_multi_doc() {
+        let source = "a: 1\n---\na: 2\n---\nb: 3\n";
+        let mut events = Vec::new();
+        let num_docs = stream_search(
+            source.chars(),
+            &parse_path("a").unwrap(),
+            Some(1),
+            TraverseOptions::default(),
+            |e| {
+                events.push(e);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(num_docs, 3);
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::DocumentStart(1),
+                StreamEvent::Match(StreamedNode {
+                    yml: Yaml::Integer(2),
+                    path: "a".to_string(),
+                    marker: Some(Marker {
+                        offset: 12,
+                        line: 3,
+                        col: 3
+                    }),
+                }),
+                StreamEvent::DocumentEnd(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_search_reports_before_document_end() {
+        let source = "a: 1\nb: [unterminated";
+        let mut matches = Vec::new();
+        let result = stream_search(
+            source.chars(),
+            &parse_path("a").unwrap(),
+            None,
+            TraverseOptions::default(),
+            |e| {
+                if let StreamEvent::Match(node) = e {
+                    matches.push(node.yml);
+                }
+                Ok(())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(matches, vec![Yaml::Integer(1)]);
+    }
+}
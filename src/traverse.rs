@@ -1,6 +1,8 @@
+use crate::error::Error;
+use crate::marker::Marker;
 use crate::path::{
-    is_child_filter, is_child_filter_value_match, matches_pattern, parse_array_child_filter,
-    parse_array_indexing_operation, ArrayIndices, ParseError, SPLAT,
+    apply_array_child_filter, is_child_filter_match, matches_pattern, ArrayIndices, FilterExpr,
+    PathSegment,
 };
 use log::{debug, error};
 use yaml_rust::yaml::{Array, Hash};
@@ -10,204 +12,313 @@ use yaml_rust::Yaml;
 pub struct VisitedNode<'a> {
     pub yml: &'a Yaml,
     pub path: String,
+    /// Where the node starts in its source, see `SourceMap::mark`.
+    pub marker: Option<Marker>,
 }
 
-fn unwrap(s: &str) -> &str {
-    if s.len() < 2 {
-        return "";
+/// How a traversal treats what its path selects, see `traverse_with`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TraverseOptions {
+    /// error with `Error::IndexOutOfRange` on an array index past the end of its array instead of
+    /// skipping it
+    pub strict: bool,
+    /// stop as soon as this many nodes have been visited
+    pub limit: Option<usize>,
+}
+
+impl TraverseOptions {
+    fn is_done(&self, visited: &[VisitedNode]) -> bool {
+        self.limit.map_or(false, |limit| visited.len() >= limit)
     }
-    &s[1..s.len() - 1]
 }
 
-fn get_array_idx<F, G>(
-    path_elem: &str,
-    array_node: &Vec<Yaml>,
+fn get_array_idx<F>(
+    head: &PathSegment,
+    array_node: &[Yaml],
     is_final_path_elem: bool,
     handle_child_filter: F,
-    handle_indexing_operation: G,
-) -> ArrayIndices
+) -> Result<ArrayIndices, Error>
 where
-    F: FnOnce(&str, &Vec<Yaml>, bool) -> Result<ArrayIndices, ParseError>,
-    G: FnOnce(&str) -> Result<ArrayIndices, ParseError>,
+    F: FnOnce(&FilterExpr, &[Yaml], bool) -> Result<ArrayIndices, Error>,
 {
-    debug!("getting array index for path_elem: `{}`", path_elem);
-    match path_elem {
-        SPLAT => {
+    debug!("getting array index for path_elem: `{:?}`", head);
+    match head {
+        PathSegment::Splat => {
             debug!("found splat for array, using all indices");
-            ArrayIndices::Star
+            Ok(ArrayIndices::Star)
         }
-        _ if is_child_filter(path_elem) => {
-            let path_elem = unwrap(path_elem);
-            handle_child_filter(path_elem, array_node, is_final_path_elem).unwrap_or_else(|err| {
-                error!("{}", err);
-                std::process::exit(1);
-            })
-        }
-        _ if path_elem.starts_with('[') && path_elem.ends_with(']') => {
-            let path_elem = unwrap(path_elem);
-            handle_indexing_operation(path_elem).unwrap_or_else(|err| {
-                error!("{}", err);
-                std::process::exit(1);
-            })
+        PathSegment::ChildFilter(filter) => {
+            handle_child_filter(filter, array_node, is_final_path_elem)
         }
+        PathSegment::Index(indices) => Ok(indices.clone()),
         _ => {
             debug!(
                 "key `{:?}` is neither a valid array index nor child filter, continuing",
-                path_elem
+                head
             );
-            ArrayIndices::Indices(vec![])
+            Ok(ArrayIndices::Indices(vec![]))
         }
     }
 }
 
+fn matches_key(k_str: &str, head: &PathSegment) -> bool {
+    match head {
+        PathSegment::Key(key) => k_str == key,
+        PathSegment::Glob(pattern) => matches_pattern(k_str, pattern),
+        PathSegment::Regex(re) => re.is_match(k_str),
+        PathSegment::Union(alternatives) => alternatives.iter().any(|alt| matches_key(k_str, alt)),
+        PathSegment::Splat => true,
+        _ => false,
+    }
+}
+
 pub fn traverse<'a>(
     node: &'a Yaml,
-    head: &str,
-    tail: &[String],
+    path: &[PathSegment],
+    visited: &mut Vec<VisitedNode<'a>>,
+) -> Result<(), Error> {
+    traverse_with(node, path, TraverseOptions::default(), visited)
+}
+
+/// Like `traverse`, but errors with `Error::IndexOutOfRange` on an array index past the end of
+/// its array instead of skipping it.
+pub fn traverse_strict<'a>(
+    node: &'a Yaml,
+    path: &[PathSegment],
+    visited: &mut Vec<VisitedNode<'a>>,
+) -> Result<(), Error> {
+    let options = TraverseOptions {
+        strict: true,
+        ..TraverseOptions::default()
+    };
+    traverse_with(node, path, options, visited)
+}
+
+/// Like `traverse`, with `options`. Once `visited` holds `options.limit` nodes the rest of the
+/// tree is not traversed, so the nodes visited are the first ones `traverse` would visit.
+pub fn traverse_with<'a>(
+    node: &'a Yaml,
+    path: &[PathSegment],
+    options: TraverseOptions,
+    visited: &mut Vec<VisitedNode<'a>>,
+) -> Result<(), Error> {
+    if options.is_done(visited) {
+        return Ok(());
+    }
+    // if the path has elements and the node is not a scalar, recurse
+    if !path.is_empty() && !is_scalar(node) {
+        recurse(
+            node,
+            &path[0],
+            &path[1..],
+            String::new(),
+            false,
+            options,
+            visited,
+        )
+    } else {
+        // the path is empty or we have a scalar, try visiting
+        visit(node, path, String::new(), visited)
+    }
+}
+
+pub(crate) fn traverse_node<'a>(
+    node: &'a Yaml,
+    head: &PathSegment,
+    tail: &[PathSegment],
     path: String,
     following_splat: bool,
+    options: TraverseOptions,
     visited: &mut Vec<VisitedNode<'a>>,
-) {
+) -> Result<(), Error> {
     // handle following a splat
     if following_splat {
-        if head == SPLAT {
-            if tail.len() > 0 {
+        return if head.is_splat() {
+            if !tail.is_empty() {
                 // first traversal after finding a splat
-                recurse(node, &tail[0], &tail[1..], path, true, visited)
+                recurse(node, &tail[0], &tail[1..], path, true, options, visited)
             } else {
                 // final path element was a splat
                 if is_scalar(node) {
-                    visit(node, tail, path, visited);
+                    visit(node, tail, path, visited)
                 } else {
-                    recurse(node, head, tail, path, false, visited);
+                    recurse(node, head, tail, path, false, options, visited)
                 }
             }
         } else if !is_scalar(node) {
             // recurse until you find a non-splat match
-            recurse(node, head, tail, path, true, visited);
-        }
-        return;
+            recurse(node, head, tail, path, true, options, visited)
+        } else {
+            Ok(())
+        };
     }
 
     // if parsed_path still has elements and the node is not a scalar, recurse
-    if tail.len() > 0 && !is_scalar(node) {
-        recurse(node, &tail[0], &tail[1..], path, false, visited)
+    if !tail.is_empty() && !is_scalar(node) {
+        recurse(node, &tail[0], &tail[1..], path, false, options, visited)
     } else {
         // the parsed path is empty or we have a scalar, try visiting
-        visit(node, tail, path, visited);
+        visit(node, tail, path, visited)
     }
 }
 
 fn is_scalar(node: &Yaml) -> bool {
-    match node {
-        Yaml::String(_) => true,
-        Yaml::Integer(_) => true,
-        Yaml::Real(_) => true,
-        Yaml::Boolean(_) => true,
-        Yaml::Null => true,
-        Yaml::BadValue => true,
-        _ => false,
-    }
+    !matches!(node, Yaml::Hash(_) | Yaml::Array(_) | Yaml::Alias(_))
 }
 
 fn recurse<'a>(
     node: &'a Yaml,
-    head: &str,
-    tail: &[String],
+    head: &PathSegment,
+    tail: &[PathSegment],
     path: String,
     following_splat: bool,
+    options: TraverseOptions,
     visited: &mut Vec<VisitedNode<'a>>,
-) {
+) -> Result<(), Error> {
+    let traverse = |node: &'a Yaml,
+                    head: &PathSegment,
+                    tail: &[PathSegment],
+                    path: String,
+                    following_splat: bool,
+                    visited: &mut Vec<VisitedNode<'a>>| {
+        traverse_node(node, head, tail, path, following_splat, options, visited)
+    };
     match node {
-        Yaml::Hash(h) => recurse_hash(h, head, tail, path, following_splat, visited, traverse),
-        Yaml::Array(v) => recurse_array(v, head, tail, path, following_splat, visited, traverse),
+        Yaml::Hash(h) => recurse_hash(
+            h,
+            head,
+            tail,
+            path,
+            following_splat,
+            options,
+            visited,
+            traverse,
+        ),
+        Yaml::Array(v) => recurse_array(
+            v,
+            head,
+            tail,
+            path,
+            following_splat,
+            options,
+            visited,
+            traverse,
+        ),
         _ => {
             error!("can only recurse on maps or arrays. recursing on `{:?}` is not supported, continuing", node);
+            Ok(())
         }
     }
 }
 
-fn extend_hash_path(p: &String, extend: &str) -> String {
-    let mut new_path = p.clone();
-    if new_path.len() > 0 {
-        new_path.push_str(".")
+pub(crate) fn extend_hash_path(p: &str, extend: &str) -> String {
+    let mut new_path = p.to_string();
+    if !new_path.is_empty() {
+        new_path.push('.')
     }
     new_path.push_str(extend);
     new_path
 }
 
+#[allow(clippy::too_many_arguments)]
 fn recurse_hash<'a, F>(
     hash: &'a Hash,
-    head: &str,
-    tail: &[String],
+    head: &PathSegment,
+    tail: &[PathSegment],
     path: String,
     following_splat: bool,
+    options: TraverseOptions,
     visited: &mut Vec<VisitedNode<'a>>,
     traverse: F,
-) where
-    F: Fn(&'a Yaml, &str, &[String], String, bool, &mut Vec<VisitedNode<'a>>),
+) -> Result<(), Error>
+where
+    F: Fn(
+        &'a Yaml,
+        &PathSegment,
+        &[PathSegment],
+        String,
+        bool,
+        &mut Vec<VisitedNode<'a>>,
+    ) -> Result<(), Error>,
 {
     for (k, v) in hash {
-        let k_str: String;
-        match k {
-            Yaml::String(s) => {
-                k_str = s.to_string();
-            }
-            Yaml::Integer(i) => {
-                k_str = i.to_string();
-            }
-            _ => {
-                error!("key `{:?}` is not valid, exiting", k);
-                std::process::exit(1);
-            }
+        if options.is_done(visited) {
+            break;
         }
+        let k_str = hash_key_str(k)?;
         if following_splat {
             // traverse deeper, still following a splat
             debug!("following splat in map for key: {}, traverse", k_str);
             let new_path = extend_hash_path(&path, &k_str);
-            traverse(v, head, tail, new_path, true, visited);
+            traverse(v, head, tail, new_path, true, visited)?;
+            if options.is_done(visited) {
+                break;
+            }
         }
-        if matches_pattern(&k_str, head) {
-            debug!("match on key: {}, traverse", &k_str);
+        if hash_entry_matches(&k_str, v, head, tail)? {
             let new_path = extend_hash_path(&path, &k_str);
-            traverse(v, head, tail, new_path, head == SPLAT, visited);
+            traverse(v, head, tail, new_path, head.is_splat(), visited)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn hash_key_str(k: &Yaml) -> Result<String, Error> {
+    match k {
+        Yaml::String(s) => Ok(s.to_string()),
+        Yaml::Integer(i) => Ok(i.to_string()),
+        _ => Err(Error::UnsupportedKey(k.clone())),
+    }
+}
+
+pub(crate) fn hash_entry_matches(
+    k_str: &str,
+    v: &Yaml,
+    head: &PathSegment,
+    tail: &[PathSegment],
+) -> Result<bool, Error> {
+    if matches_key(k_str, head) {
+        debug!("match on key: {}, traverse", k_str);
+        return Ok(true);
+    }
+    if let PathSegment::ChildFilter(filter) = head {
         // tail.len() == 0 indicates this is a final path elem
-        } else if is_child_filter(head) && tail.len() == 0 {
-            let matches = is_child_filter_value_match(v, unwrap(head)).unwrap_or_else(|err| {
-                error!("{}", err);
-                std::process::exit(1);
-            });
-            if !matches {
-                debug!("did not match on key: `{}`, continue", &k_str);
-                continue;
-            }
-            debug!("match on child value filter: `{}`", head);
-            let new_path = extend_hash_path(&path, &k_str);
-            traverse(v, head, tail, new_path, false, visited);
-        } else {
-            debug!("did not match on key: `{}`, continue", &k_str);
+        if is_child_filter_match(v, filter, tail.is_empty())? {
+            debug!("match on child filter: `{:?}`", head);
+            return Ok(true);
         }
     }
+    debug!("did not match on key: `{}`, continue", k_str);
+    Ok(false)
 }
 
-fn extend_array_path(p: &String, idx: usize) -> String {
-    let mut new_path = p.clone();
+pub(crate) fn extend_array_path(p: &str, idx: usize) -> String {
+    let mut new_path = p.to_string();
     new_path.push_str(&format!("[{}]", idx));
     new_path
 }
 
 // NOTE(wdeuschle): not testing child node filters here (out of scope for recurse_array)
-// NOTE(wdeuschle): simplify testability by making `get_array_idx` a param
+#[allow(clippy::too_many_arguments)]
 fn recurse_array<'a, F>(
     array: &'a Array,
-    head: &str,
-    tail: &[String],
+    head: &PathSegment,
+    tail: &[PathSegment],
     path: String,
     following_splat: bool,
+    options: TraverseOptions,
     visited: &mut Vec<VisitedNode<'a>>,
     traverse: F,
-) where
-    F: Fn(&'a Yaml, &str, &[String], String, bool, &mut Vec<VisitedNode<'a>>),
+) -> Result<(), Error>
+where
+    F: Fn(
+        &'a Yaml,
+        &PathSegment,
+        &[PathSegment],
+        String,
+        bool,
+        &mut Vec<VisitedNode<'a>>,
+    ) -> Result<(), Error>,
 {
     if following_splat {
         // traverse deeper, still following a splat
@@ -215,103 +326,272 @@ fn recurse_array<'a, F>(
             "following splat in array, traverse all {} indices",
             array.len()
         );
-        let array_indices: Vec<usize> = (0..array.len()).collect();
-        for array_idx in array_indices {
+        for (array_idx, array_elem) in array.iter().enumerate() {
+            if options.is_done(visited) {
+                return Ok(());
+            }
             let new_path = extend_array_path(&path, array_idx);
-            traverse(&array[array_idx], head, tail, new_path, true, visited);
+            traverse(array_elem, head, tail, new_path, true, visited)?;
         }
     }
-    let array_indices: Vec<usize> = match get_array_idx(
-        head,
-        array,
-        tail.len() == 0,
-        parse_array_child_filter,
-        parse_array_indexing_operation,
-    ) {
-        ArrayIndices::Star => (0..array.len()).collect(),
-        ArrayIndices::Indices(indices) => {
-            for i in indices.iter() {
-                if *i >= array.len() {
-                    debug!("array index {} too large, don't recurse", i);
-                    return;
-                }
-            }
-            indices
-        }
-    };
+    let array_indices = matched_array_indices(head, array, tail, &path, options.strict)?;
     debug!("match on array indices: {:?}, traverse", array_indices);
     for array_idx in array_indices {
+        if options.is_done(visited) {
+            break;
+        }
         let new_path = extend_array_path(&path, array_idx);
         traverse(
             &array[array_idx],
             head,
             tail,
             new_path,
-            head == SPLAT,
+            head.is_splat(),
             visited,
-        );
+        )?;
+    }
+    Ok(())
+}
+
+/// Indices of the elements of `array` that `head` selects. Indices past the end of the array are
+/// skipped, or an error naming the first of them if `strict`.
+fn matched_array_indices(
+    head: &PathSegment,
+    array: &[Yaml],
+    tail: &[PathSegment],
+    path: &str,
+    strict: bool,
+) -> Result<Vec<usize>, Error> {
+    let array_indices = get_array_idx(head, array, tail.is_empty(), apply_array_child_filter)?;
+    let (indices, out_of_range) = array_indices.resolve(array.len());
+    if let Some(idx) = out_of_range.first() {
+        if strict {
+            return Err(Error::IndexOutOfRange {
+                path: format!("{}[{}]", path, idx),
+                len: array.len(),
+            });
+        }
+        debug!("skipping array indices out of range: {:?}", out_of_range);
     }
+    Ok(indices)
 }
 
-fn visit<'a>(node: &'a Yaml, tail: &[String], path: String, visited: &mut Vec<VisitedNode<'a>>) {
-    if tail.len() == 0 {
+fn visit<'a>(
+    node: &'a Yaml,
+    tail: &[PathSegment],
+    path: String,
+    visited: &mut Vec<VisitedNode<'a>>,
+) -> Result<(), Error> {
+    if tail.is_empty() {
         debug!("tail length is 0, visiting leaf node {:?}", node);
-        match node {
-            s @ Yaml::String(_) => {
-                visited.push(VisitedNode {
-                    yml: s,
-                    path: path.clone(),
-                });
-            }
-            i @ Yaml::Integer(_) => {
-                visited.push(VisitedNode {
-                    yml: i,
-                    path: path.clone(),
-                });
-            }
-            f @ Yaml::Real(_) => {
-                visited.push(VisitedNode {
-                    yml: f,
-                    path: path.clone(),
-                });
-            }
-            b @ Yaml::Boolean(_) => {
-                visited.push(VisitedNode {
-                    yml: b,
-                    path: path.clone(),
-                });
+        if let Yaml::Alias(_) = node {
+            return Err(Error::UnsupportedNode(node.clone()));
+        }
+        visited.push(VisitedNode {
+            yml: node,
+            path,
+            marker: None,
+        });
+        return Ok(());
+    }
+    debug!("tail length is not 0, not visiting node {:?}", node);
+    Ok(())
+}
+
+/// What a mutable traversal should do with a node once its visitor has run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisitAction {
+    Keep,
+    Remove,
+}
+
+/// Like `traverse`, but hands each matching node and its path to `visitor` mutably, so callers can
+/// rewrite or replace matches in place. Matching follows the same rules as `traverse`, so a node
+/// may be visited more than once when following a splat. Nodes the visitor returns
+/// `VisitAction::Remove` for are removed from their parent once all of the parent's children have
+/// been traversed; removing the root node replaces it with null.
+pub fn traverse_mut<F>(node: &mut Yaml, path: &[PathSegment], visitor: &mut F) -> Result<(), Error>
+where
+    F: FnMut(&mut Yaml, &str) -> VisitAction,
+{
+    traverse_mut_with(node, path, TraverseOptions::default(), visitor)
+}
+
+/// Like `traverse_mut`, with `options`. Once `options.limit` nodes have been handed to `visitor`
+/// the matches after them are left alone.
+pub fn traverse_mut_with<F>(
+    node: &mut Yaml,
+    path: &[PathSegment],
+    options: TraverseOptions,
+    visitor: &mut F,
+) -> Result<(), Error>
+where
+    F: FnMut(&mut Yaml, &str) -> VisitAction,
+{
+    let mut remaining = options.limit;
+    let visitor = &mut |node: &mut Yaml, path: &str| match &mut remaining {
+        Some(0) => VisitAction::Keep,
+        Some(remaining) => {
+            *remaining -= 1;
+            visitor(node, path)
+        }
+        None => visitor(node, path),
+    };
+    let action = if !path.is_empty() && !is_scalar(node) {
+        recurse_mut(
+            node,
+            &path[0],
+            &path[1..],
+            String::new(),
+            false,
+            options.strict,
+            visitor,
+        )?;
+        VisitAction::Keep
+    } else {
+        visit_mut(node, path, String::new(), visitor)?
+    };
+    if action == VisitAction::Remove {
+        *node = Yaml::Null;
+    }
+    Ok(())
+}
+
+fn traverse_node_mut<F>(
+    node: &mut Yaml,
+    head: &PathSegment,
+    tail: &[PathSegment],
+    path: String,
+    following_splat: bool,
+    strict: bool,
+    visitor: &mut F,
+) -> Result<VisitAction, Error>
+where
+    F: FnMut(&mut Yaml, &str) -> VisitAction,
+{
+    if following_splat {
+        return if head.is_splat() {
+            if !tail.is_empty() {
+                recurse_mut(node, &tail[0], &tail[1..], path, true, strict, visitor)
+            } else if is_scalar(node) {
+                visit_mut(node, tail, path, visitor)
+            } else {
+                recurse_mut(node, head, tail, path, false, strict, visitor)
             }
-            h @ Yaml::Hash(_) => {
-                visited.push(VisitedNode {
-                    yml: h,
-                    path: path.clone(),
-                });
+        } else if !is_scalar(node) {
+            recurse_mut(node, head, tail, path, true, strict, visitor)
+        } else {
+            Ok(VisitAction::Keep)
+        };
+    }
+
+    if !tail.is_empty() && !is_scalar(node) {
+        recurse_mut(node, &tail[0], &tail[1..], path, false, strict, visitor)
+    } else {
+        visit_mut(node, tail, path, visitor)
+    }
+}
+
+fn recurse_mut<F>(
+    node: &mut Yaml,
+    head: &PathSegment,
+    tail: &[PathSegment],
+    path: String,
+    following_splat: bool,
+    strict: bool,
+    visitor: &mut F,
+) -> Result<VisitAction, Error>
+where
+    F: FnMut(&mut Yaml, &str) -> VisitAction,
+{
+    match node {
+        Yaml::Hash(hash) => {
+            let mut removed_keys = Vec::new();
+            for (k, v) in hash.iter_mut() {
+                let k_str = hash_key_str(k)?;
+                let mut action = VisitAction::Keep;
+                if following_splat {
+                    let new_path = extend_hash_path(&path, &k_str);
+                    if traverse_node_mut(v, head, tail, new_path, true, strict, visitor)?
+                        == VisitAction::Remove
+                    {
+                        action = VisitAction::Remove;
+                    }
+                }
+                if hash_entry_matches(&k_str, v, head, tail)? {
+                    let new_path = extend_hash_path(&path, &k_str);
+                    if traverse_node_mut(v, head, tail, new_path, head.is_splat(), strict, visitor)?
+                        == VisitAction::Remove
+                    {
+                        action = VisitAction::Remove;
+                    }
+                }
+                if action == VisitAction::Remove {
+                    removed_keys.push(k.clone());
+                }
             }
-            n @ Yaml::Null => {
-                visited.push(VisitedNode {
-                    yml: n,
-                    path: path.clone(),
-                });
+            for k in removed_keys {
+                debug!("removing hash entry with key {:?}", k);
+                hash.remove(&k);
             }
-            b @ Yaml::BadValue => {
-                visited.push(VisitedNode {
-                    yml: b,
-                    path: path.clone(),
-                });
+        }
+        Yaml::Array(array) => {
+            let mut removed_indices = Vec::new();
+            if following_splat {
+                for (array_idx, array_elem) in array.iter_mut().enumerate() {
+                    let new_path = extend_array_path(&path, array_idx);
+                    if traverse_node_mut(array_elem, head, tail, new_path, true, strict, visitor)?
+                        == VisitAction::Remove
+                    {
+                        removed_indices.push(array_idx);
+                    }
+                }
             }
-            v @ Yaml::Array(_) => {
-                visited.push(VisitedNode {
-                    yml: v,
-                    path: path.clone(),
-                });
+            for array_idx in matched_array_indices(head, array, tail, &path, strict)? {
+                let new_path = extend_array_path(&path, array_idx);
+                let array_elem = &mut array[array_idx];
+                if traverse_node_mut(
+                    array_elem,
+                    head,
+                    tail,
+                    new_path,
+                    head.is_splat(),
+                    strict,
+                    visitor,
+                )? == VisitAction::Remove
+                {
+                    removed_indices.push(array_idx);
+                }
             }
-            _a @ Yaml::Alias(_) => {
-                panic!("alias type node yet implemented");
+            // remove back to front, so the indices still to be removed stay valid
+            removed_indices.sort_unstable();
+            removed_indices.dedup();
+            for array_idx in removed_indices.into_iter().rev() {
+                debug!("removing array element at index {}", array_idx);
+                array.remove(array_idx);
             }
         }
-        return;
+        _ => {}
     }
-    debug!("tail length is not 0, not visiting node {:?}", node);
+    Ok(VisitAction::Keep)
+}
+
+fn visit_mut<F>(
+    node: &mut Yaml,
+    tail: &[PathSegment],
+    path: String,
+    visitor: &mut F,
+) -> Result<VisitAction, Error>
+where
+    F: FnMut(&mut Yaml, &str) -> VisitAction,
+{
+    if tail.is_empty() {
+        if let Yaml::Alias(_) = node {
+            return Err(Error::UnsupportedNode(node.clone()));
+        }
+        return Ok(visitor(node, &path));
+    }
+    Ok(VisitAction::Keep)
 }
 
 #[cfg(test)]
@@ -319,47 +599,50 @@ mod tests {
     use super::*;
     use yaml_rust::YamlLoader;
 
+    fn segment(s: &str) -> PathSegment {
+        crate::path::parse_path(s).unwrap().remove(0)
+    }
+
     #[test]
     fn get_array_idx_splat() {
         assert_eq!(
             ArrayIndices::Star,
             get_array_idx(
-                "**",
-                &vec![Yaml::Null],
+                &PathSegment::Splat,
+                &[Yaml::Null],
                 false,
-                parse_array_child_filter,
-                parse_array_indexing_operation
+                apply_array_child_filter
             )
+            .unwrap()
         );
     }
 
     #[test]
-    fn get_array_idx_calls_parse_array_child_filter() {
+    fn get_array_idx_calls_apply_array_child_filter() {
         let ret_val = 1;
         assert_eq!(
             ArrayIndices::Indices(vec![ret_val]),
             get_array_idx(
-                "(.==crab)",
-                &vec![Yaml::Null],
+                &segment("(.==crab)"),
+                &[Yaml::Null],
                 false,
-                |_: &str, _: &Vec<Yaml>, _: bool| Ok(ArrayIndices::Indices(vec![ret_val])),
-                parse_array_indexing_operation
+                |_: &FilterExpr, _: &[Yaml], _: bool| Ok(ArrayIndices::Indices(vec![ret_val])),
             )
+            .unwrap()
         );
     }
 
     #[test]
-    fn get_array_idx_calls_parse_array_indexing_operation() {
-        let ret_val = 1;
+    fn get_array_idx_uses_parsed_index() {
         assert_eq!(
-            ArrayIndices::Indices(vec![ret_val]),
+            ArrayIndices::Indices(vec![2]),
             get_array_idx(
-                "[2]",
-                &vec![Yaml::Null],
+                &segment("[2]"),
+                &[Yaml::Null],
                 false,
-                parse_array_child_filter,
-                |_: &str| Ok(ArrayIndices::Indices(vec![ret_val])),
+                apply_array_child_filter
             )
+            .unwrap()
         );
     }
 
@@ -368,25 +651,23 @@ mod tests {
         assert_eq!(
             ArrayIndices::Indices(vec![]),
             get_array_idx(
-                "crabby",
-                &vec![Yaml::Null],
+                &segment("crabby"),
+                &[Yaml::Null],
                 false,
-                parse_array_child_filter,
-                parse_array_indexing_operation
+                apply_array_child_filter
             )
+            .unwrap()
         );
     }
 
     #[test]
-    fn test_unwrap() {
-        let s1 = "";
-        assert_eq!("", unwrap(s1));
-
-        let s2 = "(crabby)";
-        assert_eq!("crabby", unwrap(s2));
-
-        let s3 = "[crabby]";
-        assert_eq!("crabby", unwrap(s3));
+    fn test_matches_key() {
+        assert!(matches_key("crabby", &segment("crabby")));
+        assert!(matches_key("crabby", &segment("crab*")));
+        assert!(matches_key("crabby", &PathSegment::Splat));
+        assert!(!matches_key("crabby", &segment("crab")));
+        assert!(!matches_key("crabby", &segment("\"crab*\"")));
+        assert!(!matches_key("crabby", &segment("[0]")));
     }
 
     #[test]
@@ -395,10 +676,11 @@ mod tests {
         let node = Yaml::String(String::from(""));
         visit(
             &node,
-            &[String::from("crab")],
+            &[PathSegment::Key(String::from("crab"))],
             String::from(""),
             &mut visited,
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 0);
     }
 
@@ -408,12 +690,7 @@ mod tests {
         assert_eq!(visited.len(), 0);
 
         let node = Yaml::String(String::from("crab"));
-        visit(
-            &node,
-            &[],
-            String::from(format!("path {}", visited.len())),
-            &mut visited,
-        );
+        visit(&node, &[], format!("path {}", visited.len()), &mut visited).unwrap();
         assert_eq!(visited.len(), 1);
         assert_eq!(visited[visited.len() - 1].yml, &node);
         assert_eq!(
@@ -422,12 +699,7 @@ mod tests {
         );
 
         let node = Yaml::Integer(1);
-        visit(
-            &node,
-            &[],
-            String::from(format!("path {}", visited.len())),
-            &mut visited,
-        );
+        visit(&node, &[], format!("path {}", visited.len()), &mut visited).unwrap();
         assert_eq!(visited.len(), 2);
         assert_eq!(visited[visited.len() - 1].yml, &node);
         assert_eq!(
@@ -436,12 +708,7 @@ mod tests {
         );
 
         let node = Yaml::Real(0.01.to_string());
-        visit(
-            &node,
-            &[],
-            String::from(format!("path {}", visited.len())),
-            &mut visited,
-        );
+        visit(&node, &[], format!("path {}", visited.len()), &mut visited).unwrap();
         assert_eq!(visited.len(), 3);
         assert_eq!(visited[visited.len() - 1].yml, &node);
         assert_eq!(
@@ -450,12 +717,7 @@ mod tests {
         );
 
         let node = Yaml::Boolean(true);
-        visit(
-            &node,
-            &[],
-            String::from(format!("path {}", visited.len())),
-            &mut visited,
-        );
+        visit(&node, &[], format!("path {}", visited.len()), &mut visited).unwrap();
         assert_eq!(visited.len(), 4);
         assert_eq!(visited[visited.len() - 1].yml, &node);
         assert_eq!(
@@ -471,12 +733,7 @@ mod tests {
         };
 
         let node = hash;
-        visit(
-            &node,
-            &[],
-            String::from(format!("path {}", visited.len())),
-            &mut visited,
-        );
+        visit(node, &[], format!("path {}", visited.len()), &mut visited).unwrap();
         assert_eq!(visited.len(), 5);
         assert_eq!(visited[visited.len() - 1].yml, node);
         assert_eq!(
@@ -492,12 +749,7 @@ mod tests {
         };
 
         let node = array;
-        visit(
-            &node,
-            &[],
-            String::from(format!("path {}", visited.len())),
-            &mut visited,
-        );
+        visit(node, &[], format!("path {}", visited.len()), &mut visited).unwrap();
         assert_eq!(visited.len(), 6);
         assert_eq!(visited[visited.len() - 1].yml, node);
         assert_eq!(
@@ -506,12 +758,7 @@ mod tests {
         );
 
         let node = Yaml::Null;
-        visit(
-            &node,
-            &[],
-            String::from(format!("path {}", visited.len())),
-            &mut visited,
-        );
+        visit(&node, &[], format!("path {}", visited.len()), &mut visited).unwrap();
         assert_eq!(visited.len(), 7);
         assert_eq!(visited[visited.len() - 1].yml, &node);
         assert_eq!(
@@ -520,12 +767,7 @@ mod tests {
         );
 
         let node = Yaml::BadValue;
-        visit(
-            &node,
-            &[],
-            String::from(format!("path {}", visited.len())),
-            &mut visited,
-        );
+        visit(&node, &[], format!("path {}", visited.len()), &mut visited).unwrap();
         assert_eq!(visited.len(), 8);
         assert_eq!(visited[visited.len() - 1].yml, &node);
         assert_eq!(
@@ -555,7 +797,7 @@ mod tests {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = "";
+        let head = &PathSegment::Key(String::new());
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (true, true);
@@ -567,10 +809,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -578,9 +821,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 1);
         assert_eq!(visited[0].yml, &visited_node);
         let key = match hash.front().unwrap().0 {
@@ -598,7 +844,7 @@ mod tests {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = "";
+        let head = &PathSegment::Key(String::new());
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (true, true);
@@ -610,10 +856,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -621,9 +868,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 1);
         assert_eq!(visited[0].yml, &visited_node);
         let key = match hash.front().unwrap().0 {
@@ -641,7 +891,7 @@ mod tests {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = "a";
+        let head = &segment("a");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat_first, becomes_splat_second) = (true, true, false);
@@ -653,14 +903,15 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
-                if inner_visited.len() == 0 {
+                if inner_visited.is_empty() {
                     assert_eq!(still_splat, becomes_splat_first)
                 } else {
                     assert_eq!(still_splat, becomes_splat_second)
@@ -668,9 +919,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 2);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[1].yml, &visited_node);
@@ -690,7 +944,7 @@ mod tests {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = "a";
+        let head = &segment("a");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -702,10 +956,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -713,9 +968,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 1);
         assert_eq!(visited[0].yml, &visited_node);
         let key = match hash.front().unwrap().0 {
@@ -733,7 +991,7 @@ mod tests {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = SPLAT;
+        let head = &PathSegment::Splat;
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, true);
@@ -745,10 +1003,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -756,9 +1015,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 1);
         assert_eq!(visited[0].yml, &visited_node);
         let key = match hash.front().unwrap().0 {
@@ -776,7 +1038,7 @@ mod tests {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = "(.==b)";
+        let head = &segment("(.==b)");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -788,10 +1050,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -799,9 +1062,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 1);
         assert_eq!(visited[0].yml, &visited_node);
         let key = match hash.front().unwrap().0 {
@@ -812,15 +1078,15 @@ mod tests {
     }
 
     #[test]
-    fn test_recurse_hash_is_matching_child_filter_without_empty_tail() {
+    fn test_recurse_hash_is_matching_child_filter_with_tail() {
         let hash_str = "a: b";
         let hash_yaml = &YamlLoader::load_from_str(hash_str).unwrap()[0];
         let hash = match hash_yaml {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = "(.==b)";
-        let tail = &["not empty".to_string()];
+        let head = &segment("(.==b)");
+        let tail = &[PathSegment::Key("not empty".to_string())];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
         let mut visited = Vec::<VisitedNode>::new();
@@ -831,10 +1097,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -842,10 +1109,14 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
-        assert_eq!(visited.len(), 0);
+        )
+        .unwrap();
+        assert_eq!(visited.len(), 1);
+        assert_eq!(visited[0].path, format!("{}.a", path));
     }
 
     #[test]
@@ -856,7 +1127,7 @@ mod tests {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = "(.==c)";
+        let head = &segment("(.==c)");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -868,10 +1139,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -879,9 +1151,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 0);
     }
 
@@ -893,7 +1168,7 @@ mod tests {
             Yaml::Hash(h) => h,
             _ => panic!("invalid, not hash type"),
         };
-        let head = "b";
+        let head = &segment("b");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -905,10 +1180,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -916,9 +1192,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 0);
     }
 
@@ -932,7 +1211,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = "";
+        let head = &PathSegment::Key(String::new());
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (true, true);
@@ -944,10 +1223,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -955,9 +1235,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 2);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[1].yml, &visited_node);
@@ -967,7 +1250,7 @@ mod tests {
 
     #[test]
     fn test_extend_array_path() {
-        assert_eq!(extend_array_path(&"path".to_string(), 0), "path[0]");
+        assert_eq!(extend_array_path("path", 0), "path[0]");
     }
 
     #[test]
@@ -980,7 +1263,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = SPLAT;
+        let head = &PathSegment::Splat;
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (true, true);
@@ -992,10 +1275,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1003,9 +1287,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 4);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[1].yml, &visited_node);
@@ -1028,7 +1315,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = SPLAT;
+        let head = &PathSegment::Splat;
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, true);
@@ -1040,10 +1327,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1051,9 +1339,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 2);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[1].yml, &visited_node);
@@ -1071,7 +1362,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = "[1]";
+        let head = &segment("[1]");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat_first, becomes_splat_second) = (true, true, false);
@@ -1083,10 +1374,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1098,9 +1390,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 3);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[1].yml, &visited_node);
@@ -1121,7 +1416,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = "[*]";
+        let head = &segment("[*]");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -1133,10 +1428,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1144,9 +1440,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 2);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[1].yml, &visited_node);
@@ -1164,7 +1463,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = "[1]";
+        let head = &segment("[1]");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -1176,10 +1475,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1187,9 +1487,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 1);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[0].path, format!("{}[1]", path));
@@ -1205,7 +1508,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = "[2]";
+        let head = &segment("[2]");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -1217,10 +1520,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1228,9 +1532,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 0);
     }
 
@@ -1245,7 +1552,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = "(.==crab*)";
+        let head = &segment("(.==crab*)");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -1257,10 +1564,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1268,9 +1576,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 2);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[1].yml, &visited_node);
@@ -1288,7 +1599,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = "(.==bear*)";
+        let head = &segment("(.==bear*)");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -1300,10 +1611,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1311,9 +1623,12 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 0);
     }
 
@@ -1327,7 +1642,7 @@ mod tests {
             Yaml::Array(a) => a,
             _ => panic!("invalid, not an array type"),
         };
-        let head = "(.==*)";
+        let head = &segment("(.==*)");
         let tail = &[];
         let path = "start";
         let (following_splat, becomes_splat) = (false, false);
@@ -1339,10 +1654,11 @@ mod tests {
             tail,
             String::from(path),
             following_splat,
+            TraverseOptions::default(),
             &mut visited,
             |_: &Yaml,
-             _: &str,
-             _: &[String],
+             _: &PathSegment,
+             _: &[PathSegment],
              path: String,
              still_splat: bool,
              inner_visited: &mut Vec<VisitedNode>| {
@@ -1350,13 +1666,62 @@ mod tests {
                 inner_visited.push(VisitedNode {
                     yml: &visited_node,
                     path,
+                    marker: None,
                 });
+                Ok(())
             },
-        );
+        )
+        .unwrap();
         assert_eq!(visited.len(), 2);
         assert_eq!(visited[0].yml, &visited_node);
         assert_eq!(visited[1].yml, &visited_node);
         assert_eq!(visited[0].path, format!("{}[0]", path));
         assert_eq!(visited[1].path, format!("{}[1]", path));
     }
+
+    #[test]
+    fn test_traverse_mut_paths_match_traverse() {
+        let docs_str = "
+a:
+  - b: 1
+  - c:
+      b: 2
+d:
+  b: 3";
+        let mut doc = YamlLoader::load_from_str(docs_str).unwrap().remove(0);
+        let path = crate::path::parse_path("**.b").unwrap();
+
+        let mut visited = Vec::<VisitedNode>::new();
+        let original = doc.clone();
+        traverse(&original, &path, &mut visited).unwrap();
+        let expected: Vec<String> = visited.into_iter().map(|v| v.path).collect();
+
+        let mut paths = Vec::<String>::new();
+        traverse_mut(&mut doc, &path, &mut |_, node_path| {
+            paths.push(node_path.to_string());
+            VisitAction::Keep
+        })
+        .unwrap();
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn test_traverse_mut_removes_after_visiting_children() {
+        let mut doc = YamlLoader::load_from_str("- a\n- b\n- c")
+            .unwrap()
+            .remove(0);
+        let path = crate::path::parse_path("[*]").unwrap();
+        let mut paths = Vec::<String>::new();
+        traverse_mut(&mut doc, &path, &mut |node, node_path| {
+            paths.push(node_path.to_string());
+            if *node == Yaml::String("b".to_string()) {
+                VisitAction::Keep
+            } else {
+                VisitAction::Remove
+            }
+        })
+        .unwrap();
+        assert_eq!(paths, vec!["[0]", "[1]", "[2]"]);
+        assert_eq!(doc, Yaml::Array(vec![Yaml::String("b".to_string())]));
+    }
 }
@@ -0,0 +1,267 @@
+use crate::error::Error;
+use crate::path::{type_name, ArrayIndices, PathSegment};
+use crate::traverse::{hash_key_str, traverse_mut_with, TraverseOptions, VisitAction};
+use log::debug;
+use yaml_rust::yaml::{Array, Hash};
+use yaml_rust::{Yaml, YamlLoader};
+
+/// The largest index a write creates an array element at, so that a mistyped index cannot fill
+/// the memory with nulls.
+pub const MAX_CREATED_INDEX: usize = u16::MAX as usize;
+
+/// Parses a value given on the command line. Anything that loads as a single yaml document is
+/// used as-is (so `3`, `true`, `[a, b]` and `{a: b}` are typed), everything else is a string.
+pub fn parse_value(value: &str) -> Yaml {
+    match YamlLoader::load_from_str(value) {
+        Ok(mut docs) if docs.len() == 1 => docs.remove(0),
+        _ => Yaml::String(value.to_string()),
+    }
+}
+
+/// Sets every node in `doc` matching `path` to `value`. Missing map keys and array elements named
+/// by the trailing plain keys and indices of the path are created first, so `a.b[1].c` can be
+/// written into an empty document. Nothing is created at or before a wildcard, splat or filter, as
+/// there would be nothing to write beneath it. Creating a key or index beneath a node of another
+/// type named by a plain path, e.g. a key under a scalar, is an error; beneath a wildcard, splat or
+/// filter, only the matches that can hold the new child are written to.
+pub fn write(doc: &mut Yaml, path: &[PathSegment], value: &Yaml) -> Result<(), Error> {
+    write_with(doc, path, value, TraverseOptions::default())
+}
+
+/// Like `write`, with `options`. When strict, an index past the end of an array that is not
+/// created by the write, e.g. one before a wildcard, is an error.
+pub fn write_with(
+    doc: &mut Yaml,
+    path: &[PathSegment],
+    value: &Yaml,
+    options: TraverseOptions,
+) -> Result<(), Error> {
+    let creatable_from = path
+        .iter()
+        .rposition(|segment| !is_creatable(segment))
+        .map_or(0, |idx| idx + 1);
+    let is_plain_path = creatable_from == 0;
+    for (idx, segment) in path.iter().enumerate().skip(creatable_from) {
+        let mut result = Ok(());
+        traverse_mut_with(doc, &path[..idx], options, &mut |node, node_path| {
+            if result.is_ok() {
+                result = match create_child(node, node_path, segment) {
+                    Err(Error::CannotCreate { .. }) if !is_plain_path => Ok(()),
+                    other => other,
+                };
+            }
+            VisitAction::Keep
+        })?;
+        result?;
+    }
+    traverse_mut_with(doc, path, options, &mut |node, node_path| {
+        debug!(
+            "writing value {:?} over node at `{}`: {:?}",
+            value, node_path, node
+        );
+        *node = value.clone();
+        VisitAction::Keep
+    })
+}
+
+fn is_creatable(segment: &PathSegment) -> bool {
+    matches!(
+        segment,
+        PathSegment::Key(_) | PathSegment::Index(ArrayIndices::Indices(_))
+    )
+}
+
+fn create_child(node: &mut Yaml, node_path: &str, segment: &PathSegment) -> Result<(), Error> {
+    let cannot_create = |child: String, node: &Yaml| Error::CannotCreate {
+        child,
+        node_type: match node {
+            Yaml::Hash(_) | Yaml::Array(_) => type_name(node),
+            _ => "scalar",
+        },
+        path: node_path.to_string(),
+    };
+    match segment {
+        PathSegment::Key(key) => {
+            if let Yaml::Null = node {
+                *node = Yaml::Hash(Hash::new());
+            }
+            if let Yaml::Hash(hash) = node {
+                for k in hash.keys() {
+                    if hash_key_str(k)? == *key {
+                        return Ok(());
+                    }
+                }
+                debug!("creating missing key `{}`", key);
+                hash.insert(Yaml::String(key.to_string()), Yaml::Null);
+            } else {
+                return Err(cannot_create(format!("key `{}`", key), node));
+            }
+        }
+        PathSegment::Index(ArrayIndices::Indices(indices)) => {
+            if let Yaml::Null = node {
+                *node = Yaml::Array(Array::new());
+            }
+            match (node, indices.iter().max()) {
+                (Yaml::Array(array), Some(&max_idx)) if max_idx >= array.len() => {
+                    let len = max_idx
+                        .checked_add(1)
+                        .filter(|_| max_idx <= MAX_CREATED_INDEX)
+                        .ok_or_else(|| Error::IndexTooLarge {
+                            index: max_idx,
+                            max: MAX_CREATED_INDEX,
+                            path: node_path.to_string(),
+                        })?;
+                    debug!("extending array to length {}", len);
+                    array.resize(len, Yaml::Null);
+                }
+                (Yaml::Array(_), _) => {}
+                (node, _) => {
+                    return Err(cannot_create(format!("index `{:?}`", indices), node));
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::convert_single_node;
+    use crate::path::parse_path;
+
+    fn write_str(doc_str: &str, path: &str, value: &str) -> String {
+        let mut doc = YamlLoader::load_from_str(doc_str)
+            .unwrap()
+            .pop()
+            .unwrap_or(Yaml::Null);
+        write(&mut doc, &parse_path(path).unwrap(), &parse_value(value)).unwrap();
+        convert_single_node(&doc).unwrap()
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("3"), Yaml::Integer(3));
+        assert_eq!(parse_value("crab"), Yaml::String("crab".to_string()));
+        assert_eq!(parse_value("a: b: c"), Yaml::String("a: b: c".to_string()));
+        assert_eq!(
+            parse_value("[a]"),
+            Yaml::Array(vec![Yaml::String("a".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_write_existing() {
+        assert_eq!(write_str("a:\n  b: 1", "a.b", "2"), "a:\n  b: 2");
+    }
+
+    #[test]
+    fn test_write_creates_maps() {
+        assert_eq!(write_str("", "a.b", "crab"), "a:\n  b: crab");
+        assert_eq!(write_str("a: 1", "b.c", "2"), "a: 1\nb:\n  c: 2");
+    }
+
+    #[test]
+    fn test_write_creates_arrays() {
+        assert_eq!(write_str("", "a[1].b", "crab"), "a:\n  - ~\n  - b: crab");
+    }
+
+    #[test]
+    fn test_write_wildcard() {
+        assert_eq!(
+            write_str("a:\n  b:\n    c: 1\n  d:\n    e: 2", "a.*.c", "3"),
+            "a:\n  b:\n    c: 3\n  d:\n    e: 2\n    c: 3"
+        );
+    }
+
+    #[test]
+    fn test_write_splat_does_not_create() {
+        assert_eq!(
+            write_str("a:\n  b:\n    c: 1\n  d: 2", "a.**.c", "3"),
+            "a:\n  b:\n    c: 3\n  d: 2"
+        );
+    }
+
+    #[test]
+    fn test_write_wildcard_does_not_create() {
+        assert_eq!(write_str("a: 1", "b[*].c", "2"), "a: 1");
+        assert_eq!(write_str("a: 1", "b.*.c", "2"), "a: 1");
+    }
+
+    #[test]
+    fn test_write_child_filter() {
+        assert_eq!(
+            write_str("- name: a\n  v: 1\n- name: b\n  v: 2", "(name==b).v", "3"),
+            "- name: a\n  v: 1\n- name: b\n  v: 3"
+        );
+    }
+
+    fn write_err(doc_str: &str, path: &str, value: &str) -> String {
+        let mut doc = YamlLoader::load_from_str(doc_str).unwrap().remove(0);
+        write(&mut doc, &parse_path(path).unwrap(), &parse_value(value))
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_write_scalar_in_the_way() {
+        assert_eq!(
+            write_err("a:\n  b: x", "a.b.c", "1"),
+            "cannot create key `c` under scalar at `a.b`"
+        );
+        assert_eq!(
+            write_err("a: [1]", "a.b", "1"),
+            "cannot create key `b` under seq at `a`"
+        );
+        assert_eq!(
+            write_err("a: {b: 1}", "a[0]", "1"),
+            "cannot create index `[0]` under map at `a`"
+        );
+    }
+
+    #[test]
+    fn test_write_strict() {
+        let strict = TraverseOptions {
+            strict: true,
+            ..TraverseOptions::default()
+        };
+        let mut doc = YamlLoader::load_from_str("a: [{b: 1}]").unwrap().remove(0);
+        write_with(
+            &mut doc,
+            &parse_path("a[2].b").unwrap(),
+            &Yaml::Integer(2),
+            strict,
+        )
+        .unwrap();
+        assert_eq!(
+            write_with(
+                &mut doc,
+                &parse_path("a[5].*").unwrap(),
+                &Yaml::Integer(3),
+                strict
+            ),
+            Err(Error::IndexOutOfRange {
+                path: "a[5]".to_string(),
+                len: 3
+            })
+        );
+        assert_eq!(
+            convert_single_node(&doc).unwrap(),
+            "a:\n  - b: 1\n  - ~\n  - b: 2"
+        );
+    }
+
+    #[test]
+    fn test_write_index_too_large() {
+        assert_eq!(
+            write_err("a: [1]", "a[9223372036854775807]", "1"),
+            "cannot create index `9223372036854775807` at `a`, arrays are only grown up to index 65535"
+        );
+        assert_eq!(
+            write_err("a: [1]", "a[1000000000].b", "1"),
+            "cannot create index `1000000000` at `a`, arrays are only grown up to index 65535"
+        );
+        assert_eq!(write_str("", "a[65535]", "1").lines().count(), 65537);
+    }
+}
@@ -1,35 +1,43 @@
-use ry::{convert_length, convert_single_node};
+#![allow(clippy::needless_borrow, clippy::bool_assert_comparison)]
+
+use ry::{convert_json, convert_length, convert_single_node};
 use yaml_rust::{Yaml, YamlLoader};
 
 #[test]
 fn test_convert_single_node() {
     assert_eq!(
-        convert_single_node(&Yaml::String("string".to_string())),
+        convert_single_node(&Yaml::String("string".to_string())).unwrap(),
         "string"
     );
-    assert_eq!(convert_single_node(&Yaml::Integer(1)), "1");
-    assert_eq!(convert_single_node(&Yaml::Real(0.01.to_string())), "0.01");
-    assert_eq!(convert_single_node(&Yaml::Boolean(true)), "true");
+    assert_eq!(convert_single_node(&Yaml::Integer(1)).unwrap(), "1");
+    assert_eq!(
+        convert_single_node(&Yaml::Real(0.01.to_string())).unwrap(),
+        "0.01"
+    );
+    assert_eq!(convert_single_node(&Yaml::Boolean(true)).unwrap(), "true");
     let hash_str = "a: b";
     let hash = &YamlLoader::load_from_str(hash_str).unwrap()[0];
     match hash {
         Yaml::Hash(_) => {}
         _ => panic!("invalid, not hash type"),
     };
-    assert_eq!(convert_single_node(hash), hash_str);
+    assert_eq!(convert_single_node(hash).unwrap(), hash_str);
     let array_str = "- a";
     let array = &YamlLoader::load_from_str(array_str).unwrap()[0];
     match array {
         Yaml::Array(_) => {}
         _ => panic!("invalid, not array type"),
     };
-    assert_eq!(convert_single_node(array), array_str);
-    assert_eq!(convert_single_node(&Yaml::Null), "null");
+    assert_eq!(convert_single_node(array).unwrap(), array_str);
+    assert_eq!(convert_single_node(&Yaml::Null).unwrap(), "null");
 }
 
 #[test]
 fn test_convert_length() {
-    assert_eq!(convert_length(&Yaml::String("four".to_string())), "4");
+    assert_eq!(
+        convert_length(&Yaml::String("four".to_string())).unwrap(),
+        "4"
+    );
 
     let hash_str = "
 a:
@@ -39,20 +47,48 @@ item_c
 c:
 item_d";
     let hash = &YamlLoader::load_from_str(&hash_str).unwrap()[0];
-    assert_eq!(convert_length(&hash), "3");
+    assert_eq!(convert_length(&hash).unwrap(), "3");
 
     let array_str = "
 - a
 - b
 - c";
     let array = &YamlLoader::load_from_str(&array_str).unwrap()[0];
-    assert_eq!(convert_length(&array), "3");
+    assert_eq!(convert_length(&array).unwrap(), "3");
+
+    assert_eq!(convert_length(&Yaml::Integer(100)).unwrap(), "3");
+
+    assert_eq!(
+        convert_length(&Yaml::Real(".001".to_string())).unwrap(),
+        "4"
+    );
 
-    assert_eq!(convert_length(&Yaml::Integer(100)), "3");
+    assert_eq!(convert_length(&Yaml::Boolean(true)).unwrap(), "4");
 
-    assert_eq!(convert_length(&Yaml::Real(".001".to_string())), "4");
+    assert_eq!(convert_length(&Yaml::Null).unwrap(), "0");
+}
 
-    assert_eq!(convert_length(&Yaml::Boolean(true)), "4");
+#[test]
+fn test_convert_alias_errs() {
+    assert!(convert_single_node(&Yaml::Alias(0)).is_err());
+    assert!(convert_length(&Yaml::Alias(0)).is_err());
+}
 
-    assert_eq!(convert_length(&Yaml::Null), "0");
+#[test]
+fn test_convert_json() {
+    let doc_str = "
+a:
+  b: [1, 2.5, true, ~]
+  c: \"quote \\\" and\\ttab\"
+";
+    let doc = &YamlLoader::load_from_str(doc_str).unwrap()[0];
+    assert_eq!(
+        convert_json(doc, true).unwrap(),
+        r#"{"a":{"b":[1,2.5,true,null],"c":"quote \" and\ttab"}}"#
+    );
+    assert_eq!(
+        convert_json(&doc["a"]["b"], false).unwrap(),
+        "[\n  1,\n  2.5,\n  true,\n  null\n]"
+    );
+    assert!(convert_json(&Yaml::Alias(0), false).is_err());
 }
@@ -0,0 +1,38 @@
+use ry::{convert_docs, delete, parse_path};
+use yaml_rust::YamlLoader;
+
+#[test]
+fn test_delete_multi_doc() {
+    let mut docs = YamlLoader::load_from_str("a:\n  b: 1\n---\na:\n  b: 2").unwrap();
+    let path = parse_path("a.b").unwrap();
+    delete(&mut docs[0], &path).unwrap();
+    assert_eq!(convert_docs(&docs).unwrap(), "a: {}\n---\na:\n  b: 2\n");
+}
+
+#[test]
+fn test_delete_child_filter_parents() {
+    let doc_str = "
+a:
+  - b:
+      c: magic
+    d: crab
+  - b:
+      c: magically
+    d: bear
+  - b:
+      c: magic
+    d: more crab";
+    let mut docs = YamlLoader::load_from_str(doc_str).unwrap();
+    delete(&mut docs[0], &parse_path("a.(b.c==magic)").unwrap()).unwrap();
+    assert_eq!(
+        convert_docs(&docs).unwrap(),
+        "a:\n  - b:\n      c: magically\n    d: bear\n"
+    );
+}
+
+#[test]
+fn test_delete_multiple_array_elements() {
+    let mut docs = YamlLoader::load_from_str("- a\n- b\n- a\n- d").unwrap();
+    delete(&mut docs[0], &parse_path("(.==a)").unwrap()).unwrap();
+    assert_eq!(convert_docs(&docs).unwrap(), "- b\n- d\n");
+}
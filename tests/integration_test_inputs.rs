@@ -0,0 +1,51 @@
+use ry::{expand_inputs, Error};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Creates an empty directory for a test, removing what an earlier run left behind.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ry_inputs_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn touch(dir: &Path, file: &str) -> String {
+    let path = dir.join(file);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, "a: 1\n").unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_expand_inputs_walks_directories_in_order() {
+    let dir = test_dir("walk");
+    let b = touch(&dir, "charts/b/values.yaml");
+    let a = touch(&dir, "charts/a/values.yml");
+    let json = touch(&dir, "charts/data.JSON");
+    touch(&dir, "charts/README.md");
+    let top = touch(&dir, "values.yml");
+
+    let charts = dir.join("charts");
+    let files = expand_inputs(&[&top, charts.to_str().unwrap()]).unwrap();
+    assert_eq!(files, vec![top, a, b, json]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_expand_inputs_expands_globs() {
+    let dir = test_dir("glob");
+    let api = touch(&dir, "charts/api/values.yaml");
+    let web = touch(&dir, "charts/web/values.yaml");
+    touch(&dir, "charts/web/values.yml");
+
+    let pattern = format!("{}/charts/**/*.yaml", dir.display());
+    assert_eq!(expand_inputs(&[&pattern]).unwrap(), vec![api, web]);
+
+    let missing = format!("{}/charts/**/*.json", dir.display());
+    match expand_inputs(&[&missing]) {
+        Err(Error::Input(msg)) => assert!(msg.contains("no files match")),
+        other => panic!("expected an input error, got {:?}", other),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
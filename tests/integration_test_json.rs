@@ -0,0 +1,36 @@
+use ry::{load_json, parse_path, traverse, Error, VisitedNode};
+use yaml_rust::Yaml;
+
+#[test]
+fn test_load_json_traverse() {
+    let doc_str = r#"
+{
+  "a": {
+    "item_b": {"f": 1},
+    "thing_c": {"f": "two"},
+    "item_d": {"f": [true, null]}
+  }
+}"#;
+    let doc = load_json(doc_str).unwrap();
+    let mut visited = Vec::<VisitedNode>::new();
+    traverse(&doc, &parse_path("a.item*.f").unwrap(), &mut visited).unwrap();
+    let values: Vec<&Yaml> = visited.iter().map(|v| v.yml).collect();
+    assert_eq!(
+        values,
+        vec![
+            &Yaml::Integer(1),
+            &Yaml::Array(vec![Yaml::Boolean(true), Yaml::Null])
+        ]
+    );
+}
+
+#[test]
+fn test_load_json_error() {
+    match load_json("{\n  \"a\": [1,\n  2,,\n]}") {
+        Err(Error::Json(err)) => {
+            assert_eq!((err.marker.line, err.marker.col), (3, 4));
+            assert_eq!(err.to_string(), "expected a json value at line 3 column 5");
+        }
+        other => panic!("expected a json error, got {:?}", other),
+    }
+}
@@ -0,0 +1,104 @@
+use ry::{delete, parse_path, parse_value, render_lossless, write};
+use yaml_rust::YamlLoader;
+
+#[test]
+fn test_render_lossless_write_and_delete() {
+    let source = "
+# services
+web:
+  image: 'nginx' # pinned below
+  ports: [80, 443]
+
+worker:
+  image: busybox
+";
+    let old_docs = YamlLoader::load_from_str(source).unwrap();
+    let mut new_docs = old_docs.clone();
+    write(
+        &mut new_docs[0],
+        &parse_path("web.image").unwrap(),
+        &parse_value("nginx:1.19"),
+    )
+    .unwrap();
+    delete(&mut new_docs[0], &parse_path("worker").unwrap()).unwrap();
+    assert_eq!(
+        render_lossless(source, &old_docs, &new_docs).unwrap(),
+        "
+# services
+web:
+  image: 'nginx:1.19' # pinned below
+  ports: [80, 443]
+
+"
+    );
+}
+
+#[test]
+fn test_render_lossless_keeps_crlf_line_endings() {
+    let source = "# top\r\na:\r\n  b: 1 # one\r\nc: [1, 2]\r\n";
+    let old_docs = YamlLoader::load_from_str(source).unwrap();
+    let mut new_docs = old_docs.clone();
+    write(
+        &mut new_docs[0],
+        &parse_path("a.d").unwrap(),
+        &parse_value("{e: 2}"),
+    )
+    .unwrap();
+    write(
+        &mut new_docs[0],
+        &parse_path("a.b").unwrap(),
+        &parse_value("[x]"),
+    )
+    .unwrap();
+    assert_eq!(
+        render_lossless(source, &old_docs, &new_docs).unwrap(),
+        "# top\r\na:\r\n  b:\r\n    - x\r\n  d:\r\n    e: 2\r\nc: [1, 2]\r\n"
+    );
+}
+
+#[test]
+fn test_render_lossless_delete_in_flow_collections() {
+    let source = "a:   [1,   2,  3] # nums\nb: {x: 1,  y: 2 , z: 3}\n";
+    let old_docs = YamlLoader::load_from_str(source).unwrap();
+    let mut new_docs = old_docs.clone();
+    delete(&mut new_docs[0], &parse_path("a[1]").unwrap()).unwrap();
+    delete(&mut new_docs[0], &parse_path("b.z").unwrap()).unwrap();
+    assert_eq!(
+        render_lossless(source, &old_docs, &new_docs).unwrap(),
+        "a:   [1,   3] # nums\nb: {x: 1,  y: 2}\n"
+    );
+
+    let mut new_docs = old_docs.clone();
+    delete(&mut new_docs[0], &parse_path("a[0]").unwrap()).unwrap();
+    delete(&mut new_docs[0], &parse_path("b.y").unwrap()).unwrap();
+    assert_eq!(
+        render_lossless(source, &old_docs, &new_docs).unwrap(),
+        "a:   [2,  3] # nums\nb: {x: 1,  z: 3}\n"
+    );
+}
+
+#[test]
+fn test_render_lossless_replace_every_key_of_flow_map() {
+    let source = "a: {x: 1}   # one\nb: {x: 1,  y: 2}\n";
+    let old_docs = YamlLoader::load_from_str(source).unwrap();
+    let mut new_docs = old_docs.clone();
+    for path in &["a.x", "b.x", "b.y"] {
+        delete(&mut new_docs[0], &parse_path(path).unwrap()).unwrap();
+    }
+    write(
+        &mut new_docs[0],
+        &parse_path("a.y").unwrap(),
+        &parse_value("2"),
+    )
+    .unwrap();
+    write(
+        &mut new_docs[0],
+        &parse_path("b.z").unwrap(),
+        &parse_value("[3]"),
+    )
+    .unwrap();
+    assert_eq!(
+        render_lossless(source, &old_docs, &new_docs).unwrap(),
+        "a: {y: 2}   # one\nb: {z: [3]}\n"
+    );
+}
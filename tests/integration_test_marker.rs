@@ -0,0 +1,42 @@
+use ry::{parse_path, traverse, Marker, SourceMap, VisitedNode};
+use yaml_rust::YamlLoader;
+
+#[test]
+fn test_source_map_marks_visited() {
+    let doc_str = "
+a:
+  item_b:
+    f: 1
+  thing_c:
+    f: 2
+";
+    let docs = YamlLoader::load_from_str(doc_str).unwrap();
+    let source_map = SourceMap::new(doc_str, &docs).unwrap();
+    let mut visited = Vec::<VisitedNode>::new();
+    traverse(&docs[0], &parse_path("a.*.f").unwrap(), &mut visited).unwrap();
+    source_map.mark(&mut visited);
+    let markers: Vec<Option<Marker>> = visited.iter().map(|v| v.marker).collect();
+    assert_eq!(
+        markers,
+        vec![
+            Some(Marker {
+                offset: 21,
+                line: 4,
+                col: 7
+            }),
+            Some(Marker {
+                offset: 41,
+                line: 6,
+                col: 7
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_source_map_unknown_node() {
+    let doc_str = "a: 1";
+    let docs = YamlLoader::load_from_str(doc_str).unwrap();
+    let source_map = SourceMap::new(doc_str, &docs).unwrap();
+    assert_eq!(source_map.marker(&yaml_rust::Yaml::Integer(1)), None);
+}
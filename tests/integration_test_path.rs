@@ -1,15 +1,24 @@
-use ry::parse_path;
+#![allow(clippy::needless_borrow, clippy::bool_assert_comparison)]
+
+use ry::{parse_path, ArrayIndices, ChildFilter, FilterExpr, FilterOp, PathSegment};
+
+fn key(s: &str) -> PathSegment {
+    PathSegment::Key(s.to_string())
+}
 
 #[test]
 fn test_parse_path() {
-    assert_eq!(parse_path("a.b.c").unwrap(), vec!["a", "b", "c"]);
+    assert_eq!(
+        parse_path("a.b.c").unwrap(),
+        vec![key("a"), key("b"), key("c")]
+    );
 }
 
 #[test]
 fn test_parse_path_with_quotes() {
     assert_eq!(
         parse_path("a.\"foo.bar\".c").unwrap(),
-        vec!["a", "foo.bar", "c"]
+        vec![key("a"), key("foo.bar"), key("c")]
     );
 }
 
@@ -27,7 +36,12 @@ fn test_parse_path_with_one_quote_errs() {
 fn test_parse_path_with_array_indexing() {
     assert_eq!(
         parse_path("a.foo[10].bar").unwrap(),
-        vec!["a", "foo", "[10]", "bar"]
+        vec![
+            key("a"),
+            key("foo"),
+            PathSegment::Index(ArrayIndices::Indices(vec![10])),
+            key("bar")
+        ]
     );
 }
 
@@ -35,7 +49,15 @@ fn test_parse_path_with_array_indexing() {
 fn test_parse_path_with_parens() {
     assert_eq!(
         parse_path("a.(b.d==cat*).c").unwrap(),
-        vec!["a", "(b.d==cat*)", "c"]
+        vec![
+            key("a"),
+            PathSegment::ChildFilter(FilterExpr::Cond(ChildFilter {
+                path: vec![key("b"), key("d")],
+                op: FilterOp::Eq,
+                value: "cat*".to_string(),
+            })),
+            key("c")
+        ]
     );
 }
 
@@ -83,6 +105,105 @@ fn test_parse_path_with_close_paren_start_errs() {
 fn test_parse_path_with_child_value_filtering() {
     assert_eq!(
         parse_path("animals(.==cat)").unwrap(),
-        vec!["animals", "(.==cat)"]
+        vec![
+            key("animals"),
+            PathSegment::ChildFilter(FilterExpr::Cond(ChildFilter {
+                path: vec![],
+                op: FilterOp::Eq,
+                value: "cat".to_string(),
+            }))
+        ]
+    );
+}
+
+#[test]
+fn test_parse_path_with_wildcards() {
+    assert_eq!(
+        parse_path("a.item*.**.\"b*\"[*]").unwrap(),
+        vec![
+            key("a"),
+            PathSegment::Glob("item*".to_string()),
+            PathSegment::Splat,
+            key("b*"),
+            PathSegment::Index(ArrayIndices::Star),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_path_with_invalid_array_index_errs() {
+    let result = parse_path("a.foo[x].bar");
+    assert!(result.is_err());
+    assert!(format!("{}", result.unwrap_err()).starts_with("unable to parse array index"));
+}
+
+#[test]
+fn test_parse_path_with_invalid_child_filter_errs() {
+    let result = parse_path("a.(b.d=cat).c");
+    assert!(result.is_err());
+    assert!(format!("{}", result.unwrap_err()).starts_with("invalid child filter"));
+}
+
+#[test]
+fn test_parse_path_with_comparison_filter() {
+    assert_eq!(
+        parse_path("jobs.(timeout>300).name").unwrap(),
+        vec![
+            key("jobs"),
+            PathSegment::ChildFilter(FilterExpr::Cond(ChildFilter {
+                path: vec![key("timeout")],
+                op: FilterOp::Gt,
+                value: "300".to_string(),
+            })),
+            key("name")
+        ]
+    );
+    assert!(parse_path("jobs.(timeout>ten).name").is_err());
+}
+
+#[test]
+fn test_parse_path_with_key_unions() {
+    let union = || PathSegment::Union(vec![key("containers"), key("initContainers")]);
+    assert_eq!(
+        parse_path("spec.(containers|initContainers)[*].image").unwrap(),
+        vec![
+            key("spec"),
+            union(),
+            PathSegment::Index(ArrayIndices::Star),
+            key("image")
+        ]
+    );
+    assert_eq!(
+        parse_path("spec.{containers,initContainers}.image").unwrap(),
+        vec![key("spec"), union(), key("image")]
+    );
+}
+
+#[test]
+fn test_parse_path_with_non_ascii_keys() {
+    assert_eq!(parse_path("é.x").unwrap(), vec![key("é"), key("x")]);
+    assert_eq!(
+        parse_path("naïve.\"ü.ß\"[0].(ключ==значение)").unwrap(),
+        vec![
+            key("naïve"),
+            key("ü.ß"),
+            PathSegment::Index(ArrayIndices::Indices(vec![0])),
+            PathSegment::ChildFilter(FilterExpr::Cond(ChildFilter {
+                path: vec![key("ключ")],
+                op: FilterOp::Eq,
+                value: "значение".to_string(),
+            })),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_path_with_escaped_array_open_errs() {
+    let result = parse_path("a.item\\[bc]");
+    assert!(result.is_err());
+    assert!(format!("{}", result.unwrap_err()).contains("quote the key to match `[` literally"));
+    assert_eq!(
+        parse_path("a.\"item[bc]\"").unwrap(),
+        vec![key("a"), key("item[bc]")]
     );
 }
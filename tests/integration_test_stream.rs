@@ -0,0 +1,72 @@
+use ry::{parse_path, stream_count, stream_search, StreamEvent, TraverseOptions};
+
+fn stream_values(doc_str: &str, path: &str, doc_idx: Option<usize>) -> Vec<String> {
+    let mut found = Vec::new();
+    stream_search(
+        doc_str.chars(),
+        &parse_path(path).unwrap(),
+        doc_idx,
+        TraverseOptions::default(),
+        |event| {
+            match event {
+                StreamEvent::DocumentStart(idx) => found.push(format!("start {}", idx)),
+                StreamEvent::Match(node) => found.push(format!("{}: {:?}", node.path, node.yml)),
+                StreamEvent::DocumentEnd(idx) => found.push(format!("end {}", idx)),
+            }
+            Ok(())
+        },
+    )
+    .unwrap();
+    found
+}
+
+#[test]
+fn test_stream_search_doc_idx() {
+    let doc_str = "a: 1\n---\na: 2\n---\na: 3";
+    assert_eq!(
+        stream_values(doc_str, "a", Some(1)),
+        vec!["start 1", "a: Integer(2)", "end 1"]
+    );
+}
+
+#[test]
+fn test_stream_search_anchors() {
+    let doc_str = "
+base: &base
+  name: crab
+copy: *base
+";
+    assert_eq!(
+        stream_values(doc_str, "*.name", None),
+        vec![
+            "start 0",
+            "base.name: String(\"crab\")",
+            "copy.name: String(\"crab\")",
+            "end 0"
+        ]
+    );
+}
+
+#[test]
+fn test_stream_count_per_doc() {
+    let doc_str = "
+a: [1, 2, 3]
+---
+a: [4]
+---
+b: [5, 6]";
+    let mut counts = Vec::new();
+    let num_docs = stream_count(
+        doc_str.chars(),
+        &parse_path("*[*]").unwrap(),
+        None,
+        TraverseOptions::default(),
+        |idx, count| {
+            counts.push((idx, count));
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(num_docs, 3);
+    assert_eq!(counts, vec![(0, 3), (1, 1), (2, 2)]);
+}
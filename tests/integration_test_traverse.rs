@@ -1,5 +1,8 @@
+#![allow(clippy::needless_borrow, clippy::bool_assert_comparison)]
+
 use ry::convert::convert_single_node;
-use yaml_rust::YamlLoader;
+use ry::parse_path;
+use yaml_rust::{Yaml, YamlLoader};
 
 #[test]
 fn test_traverse_leaf() {
@@ -10,16 +13,9 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "b".to_string(), "c".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.b.c").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 1);
-    assert_eq!(convert_single_node(visited[0].yml), "2");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "2");
 }
 
 #[test]
@@ -31,16 +27,9 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "b".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.b").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 1);
-    assert_eq!(convert_single_node(visited[0].yml), "c: 2");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "c: 2");
 }
 
 #[test]
@@ -52,16 +41,9 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "foo.bar".to_string(), "c".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.\"foo.bar\".c").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 1);
-    assert_eq!(convert_single_node(visited[0].yml), "2");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "2");
 }
 
 #[test]
@@ -75,16 +57,9 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "b".to_string(), "[1]".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.b[1]").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 1);
-    assert_eq!(convert_single_node(visited[0].yml), "2");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "2");
 }
 
 #[test]
@@ -98,18 +73,11 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "b".to_string(), "[*]".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.b[*]").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 3);
-    assert_eq!(convert_single_node(visited[0].yml), "1");
-    assert_eq!(convert_single_node(visited[1].yml), "2");
-    assert_eq!(convert_single_node(visited[2].yml), "3");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "1");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "2");
+    assert_eq!(convert_single_node(visited[2].yml).unwrap(), "3");
 }
 
 #[test]
@@ -123,21 +91,9 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec![
-            "a".to_string(),
-            "b".to_string(),
-            "[*]".to_string(),
-            "c".to_string(),
-        ],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.b[*].c").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 1);
-    assert_eq!(convert_single_node(visited[0].yml), "d");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "d");
 }
 
 #[test]
@@ -155,17 +111,10 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "item*".to_string(), "f".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.item*.f").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 2);
-    assert_eq!(convert_single_node(visited[0].yml), "1");
-    assert_eq!(convert_single_node(visited[1].yml), "3");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "1");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "3");
 }
 
 #[test]
@@ -183,19 +132,12 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "*".to_string(), "f".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.*.f").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 4);
-    assert_eq!(convert_single_node(visited[0].yml), "1");
-    assert_eq!(convert_single_node(visited[1].yml), "2");
-    assert_eq!(convert_single_node(visited[2].yml), "3");
-    assert_eq!(convert_single_node(visited[3].yml), "4");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "1");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "2");
+    assert_eq!(convert_single_node(visited[2].yml).unwrap(), "3");
+    assert_eq!(convert_single_node(visited[3].yml).unwrap(), "4");
 }
 
 #[test]
@@ -221,22 +163,10 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec![
-            "a".to_string(),
-            "(b.d==cat)".to_string(),
-            "b".to_string(),
-            "c".to_string(),
-        ],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.(b.d==cat).b.c").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 2);
-    assert_eq!(convert_single_node(visited[0].yml), "thing1");
-    assert_eq!(convert_single_node(visited[1].yml), "thing3");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "thing1");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "thing3");
 }
 
 #[test]
@@ -261,24 +191,283 @@ a:
     ba: also meowy";
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(doc, &parse_path("a.(b.d==cat*).b.c").unwrap(), &mut visited).unwrap();
+    assert_eq!(visited.len(), 3);
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "thing1");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "thing2");
+    assert_eq!(convert_single_node(visited[2].yml).unwrap(), "thing3");
+}
+
+#[test]
+fn test_child_map_filtering() {
+    let docs_str = "
+services:
+  web:
+    image: nginx:1.19
+    ports: [80]
+  db:
+    image: postgres:13
+    ports: [5432]
+  proxy:
+    image: nginx:alpine
+    ports: [443]";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
     let mut visited = Vec::<ry::VisitedNode>::new();
     ry::traverse(
-        &doc,
-        "",
-        &vec![
-            "a".to_string(),
-            "(b.d==cat*)".to_string(),
-            "b".to_string(),
-            "c".to_string(),
-        ],
-        String::new(),
-        false,
+        doc,
+        &parse_path("services.(image==nginx*).ports[0]").unwrap(),
         &mut visited,
-    );
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 2);
+    assert_eq!(visited[0].path, "services.web.ports[0]");
+    assert_eq!(visited[1].path, "services.proxy.ports[0]");
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path("services.(image==postgres*)").unwrap(),
+        &mut visited,
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 1);
+    assert_eq!(visited[0].path, "services.db");
+}
+
+#[test]
+fn test_regex_keys_and_filters() {
+    let docs_str = "
+items:
+  item_1: {name: web-1}
+  item_22: {name: web-x}
+  item_: {name: web-3}
+  other: {name: web-4}";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path(r"items.~/^item_\d+$/.name").unwrap(),
+        &mut visited,
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 2);
+    assert_eq!(visited[0].path, "items.item_1.name");
+    assert_eq!(visited[1].path, "items.item_22.name");
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path("items.(name=~^web-([0-9]|[3-9])$)").unwrap(),
+        &mut visited,
+    )
+    .unwrap();
     assert_eq!(visited.len(), 3);
-    assert_eq!(convert_single_node(visited[0].yml), "thing1");
-    assert_eq!(convert_single_node(visited[1].yml), "thing2");
-    assert_eq!(convert_single_node(visited[2].yml), "thing3");
+    assert_eq!(visited[0].path, "items.item_1");
+    assert_eq!(visited[1].path, "items.item_");
+    assert_eq!(visited[2].path, "items.other");
+}
+
+#[test]
+fn test_slashed_keys_are_not_regexes() {
+    let docs_str = "
+paths:
+  /api/:
+    get: 1
+  /apix/:
+    get: 2";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(doc, &parse_path("paths./api/.get").unwrap(), &mut visited).unwrap();
+    assert_eq!(visited.len(), 1);
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "1");
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path(r"paths.~/^\/api/.get").unwrap(),
+        &mut visited,
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 2);
+}
+
+#[test]
+fn test_combined_child_filters() {
+    let docs_str = "
+items:
+  - kind: Deployment
+    metadata: {name: web, namespace: default}
+  - kind: Deployment
+    metadata: {name: dns, namespace: kube-system}
+  - kind: Service
+    metadata: {name: web, namespace: default}";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path("items.(kind==Deployment && metadata.namespace!=kube-system).metadata.name")
+            .unwrap(),
+        &mut visited,
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 1);
+    assert_eq!(visited[0].path, "items[0].metadata.name");
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path("items.(!(kind==Deployment) || metadata.name==dns).kind").unwrap(),
+        &mut visited,
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 2);
+    assert_eq!(visited[0].path, "items[1].kind");
+    assert_eq!(visited[1].path, "items[2].kind");
+}
+
+#[test]
+fn test_predicate_child_filters() {
+    let docs_str = "
+containers:
+  - name: web
+    resources: {limits: {cpu: 1}}
+  - name: sidecar
+    resources: {}
+  - name: job
+config:
+  a: {x: 1}
+  b: [1]
+  c: {y: 2}";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path("containers.(has(resources.limits)).name").unwrap(),
+        &mut visited,
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 1);
+    assert_eq!(visited[0].path, "containers[0].name");
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path("containers.(!has(resources)).name").unwrap(),
+        &mut visited,
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 1);
+    assert_eq!(visited[0].path, "containers[2].name");
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(
+        doc,
+        &parse_path("config.(type(.)==map)").unwrap(),
+        &mut visited,
+    )
+    .unwrap();
+    assert_eq!(visited.len(), 2);
+    assert_eq!(visited[0].path, "config.a");
+    assert_eq!(visited[1].path, "config.c");
+}
+
+#[test]
+fn test_negative_indices_and_slices() {
+    let docs_str = "
+a: [x0, x1, x2, x3, x4]";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+    let paths = |path: &str| {
+        let mut visited = Vec::<ry::VisitedNode>::new();
+        ry::traverse(doc, &parse_path(path).unwrap(), &mut visited).unwrap();
+        visited.into_iter().map(|v| v.path).collect::<Vec<_>>()
+    };
+    assert_eq!(paths("a[-1]"), vec!["a[4]"]);
+    assert_eq!(paths("a[1:3]"), vec!["a[1]", "a[2]"]);
+    assert_eq!(paths("a[::2]"), vec!["a[0]", "a[2]", "a[4]"]);
+    assert_eq!(paths("a[0,2,-1]"), vec!["a[0]", "a[2]", "a[4]"]);
+    assert_eq!(paths("a[::-2]"), vec!["a[4]", "a[2]", "a[0]"]);
+}
+
+#[test]
+fn test_out_of_range_indices_are_skipped() {
+    let docs_str = "
+a:
+  b: [x0, x1, x2]
+  c:
+    - [1, 2]
+    - [3]";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(doc, &parse_path("a.b[0,5,-9,2]").unwrap(), &mut visited).unwrap();
+    assert_eq!(visited.len(), 2);
+    assert_eq!(visited[0].path, "a.b[0]");
+    assert_eq!(visited[1].path, "a.b[2]");
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse(doc, &parse_path("a.c[*][1]").unwrap(), &mut visited).unwrap();
+    assert_eq!(visited.len(), 1);
+    assert_eq!(visited[0].path, "a.c[0][1]");
+}
+
+#[test]
+fn test_strict_traverse_reports_out_of_range_path() {
+    let docs_str = "
+a:
+  c:
+    - [1, 2]
+    - [3]";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    ry::traverse_strict(doc, &parse_path("a.c[*][0]").unwrap(), &mut visited).unwrap();
+    assert_eq!(visited.len(), 2);
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    match ry::traverse_strict(doc, &parse_path("a.c[*][1]").unwrap(), &mut visited) {
+        Err(ry::Error::IndexOutOfRange { path, len }) => {
+            assert_eq!(path, "a.c[1][1]");
+            assert_eq!(len, 1);
+        }
+        other => panic!("expected an out of range error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_traverse_with_limit_stops_at_first_matches() {
+    let docs_str = "
+a:
+  b:
+    - c: 1
+    - c: 2
+  d:
+    c: 3
+    e:
+      c: 4";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
+    let paths = |limit| {
+        let mut visited = Vec::<ry::VisitedNode>::new();
+        let options = ry::TraverseOptions {
+            limit,
+            ..ry::TraverseOptions::default()
+        };
+        ry::traverse_with(doc, &parse_path("**.c").unwrap(), options, &mut visited).unwrap();
+        visited.into_iter().map(|v| v.path).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        paths(None),
+        vec!["a.b[0].c", "a.b[1].c", "a.d.c", "a.d.e.c"]
+    );
+    assert_eq!(paths(Some(1)), vec!["a.b[0].c"]);
+    assert_eq!(paths(Some(3)), vec!["a.b[0].c", "a.b[1].c", "a.d.c"]);
+    assert_eq!(paths(Some(10)), paths(None));
 }
 
 #[test]
@@ -306,20 +495,13 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "**".to_string(), "c".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.**.c").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 5);
-    assert_eq!(convert_single_node(visited[0].yml), "thing1");
-    assert_eq!(convert_single_node(visited[1].yml), "c: thing1");
-    assert_eq!(convert_single_node(visited[2].yml), "thing2");
-    assert_eq!(convert_single_node(visited[3].yml), "thing3");
-    assert_eq!(convert_single_node(visited[4].yml), "thing4");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "thing1");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "c: thing1");
+    assert_eq!(convert_single_node(visited[2].yml).unwrap(), "thing2");
+    assert_eq!(convert_single_node(visited[3].yml).unwrap(), "thing3");
+    assert_eq!(convert_single_node(visited[4].yml).unwrap(), "thing4");
 }
 
 #[test]
@@ -347,24 +529,17 @@ a:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["a".to_string(), "**".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("a.**").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 9);
-    assert_eq!(convert_single_node(visited[0].yml), "thing1");
-    assert_eq!(convert_single_node(visited[1].yml), "cat cat");
-    assert_eq!(convert_single_node(visited[2].yml), "thing2");
-    assert_eq!(convert_single_node(visited[3].yml), "dog dog");
-    assert_eq!(convert_single_node(visited[4].yml), "thing3");
-    assert_eq!(convert_single_node(visited[5].yml), "beep");
-    assert_eq!(convert_single_node(visited[6].yml), "thing4");
-    assert_eq!(convert_single_node(visited[7].yml), "boop");
-    assert_eq!(convert_single_node(visited[8].yml), "mooo");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "thing1");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "cat cat");
+    assert_eq!(convert_single_node(visited[2].yml).unwrap(), "thing2");
+    assert_eq!(convert_single_node(visited[3].yml).unwrap(), "dog dog");
+    assert_eq!(convert_single_node(visited[4].yml).unwrap(), "thing3");
+    assert_eq!(convert_single_node(visited[5].yml).unwrap(), "beep");
+    assert_eq!(convert_single_node(visited[6].yml).unwrap(), "thing4");
+    assert_eq!(convert_single_node(visited[7].yml).unwrap(), "boop");
+    assert_eq!(convert_single_node(visited[8].yml).unwrap(), "mooo");
 }
 
 #[test]
@@ -378,17 +553,10 @@ animals:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["animals".to_string(), "(.==c*)".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("animals.(.==c*)").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 2);
-    assert_eq!(convert_single_node(visited[0].yml), "cats");
-    assert_eq!(convert_single_node(visited[1].yml), "cheetah");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "cats");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "cheetah");
 }
 
 #[test]
@@ -404,15 +572,102 @@ animals:
     let doc = &YamlLoader::load_from_str(&docs_str).unwrap()[0];
 
     let mut visited = Vec::<ry::VisitedNode>::new();
-    ry::traverse(
-        &doc,
-        "",
-        &vec!["animals".to_string(), "(.==yes*)".to_string()],
-        String::new(),
-        false,
-        &mut visited,
-    );
+    ry::traverse(doc, &parse_path("animals.(.==yes*)").unwrap(), &mut visited).unwrap();
     assert_eq!(visited.len(), 2);
-    assert_eq!(convert_single_node(visited[0].yml), "yes");
-    assert_eq!(convert_single_node(visited[1].yml), "yessiree");
+    assert_eq!(convert_single_node(visited[0].yml).unwrap(), "yes");
+    assert_eq!(convert_single_node(visited[1].yml).unwrap(), "yessiree");
+}
+
+#[test]
+fn test_traverse_unsupported_key_errs() {
+    let docs_str = "
+a:
+  true: b";
+    let doc = &YamlLoader::load_from_str(docs_str).unwrap()[0];
+
+    let mut visited = Vec::<ry::VisitedNode>::new();
+    let result = ry::traverse(doc, &parse_path("a.b").unwrap(), &mut visited);
+    assert_eq!(
+        result.unwrap_err(),
+        ry::Error::UnsupportedKey(Yaml::Boolean(true))
+    );
+}
+
+#[test]
+fn test_traverse_mut_rewrites_in_place() {
+    let docs_str = "
+a:
+  - name: crab
+    size: 1
+  - name: bear
+    size: 2";
+    let mut doc = YamlLoader::load_from_str(docs_str).unwrap().remove(0);
+
+    let mut paths = Vec::<String>::new();
+    ry::traverse_mut(
+        &mut doc,
+        &parse_path("a[*].size").unwrap(),
+        &mut |node, path| {
+            paths.push(path.to_string());
+            if let Yaml::Integer(size) = node {
+                *size *= 10;
+            }
+            ry::VisitAction::Keep
+        },
+    )
+    .unwrap();
+    assert_eq!(paths, vec!["a[0].size", "a[1].size"]);
+    assert_eq!(
+        convert_single_node(&doc).unwrap(),
+        "a:\n  - name: crab\n    size: 10\n  - name: bear\n    size: 20"
+    );
+}
+
+#[test]
+fn test_traverse_mut_removes_matches() {
+    let docs_str = "
+a:
+  - name: crab
+  - name: bear
+  - name: crabby";
+    let mut doc = YamlLoader::load_from_str(docs_str).unwrap().remove(0);
+
+    ry::traverse_mut(
+        &mut doc,
+        &parse_path("a.(name==crab*)").unwrap(),
+        &mut |_, _| ry::VisitAction::Remove,
+    )
+    .unwrap();
+    assert_eq!(convert_single_node(&doc).unwrap(), "a:\n  - name: bear");
+}
+
+#[test]
+fn test_traverse_mut_with_options() {
+    let mut doc = YamlLoader::load_from_str("a: [1, 2, 3]").unwrap().remove(0);
+    let options = ry::TraverseOptions {
+        strict: true,
+        limit: Some(2),
+    };
+    ry::traverse_mut_with(
+        &mut doc,
+        &parse_path("a[*]").unwrap(),
+        options,
+        &mut |_, _| ry::VisitAction::Remove,
+    )
+    .unwrap();
+    assert_eq!(convert_single_node(&doc).unwrap(), "a:\n  - 3");
+
+    let out_of_range = ry::traverse_mut_with(
+        &mut doc,
+        &parse_path("a[1]").unwrap(),
+        options,
+        &mut |_, _| ry::VisitAction::Remove,
+    );
+    assert_eq!(
+        out_of_range,
+        Err(ry::Error::IndexOutOfRange {
+            path: "a[1]".to_string(),
+            len: 1
+        })
+    );
 }
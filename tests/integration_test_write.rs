@@ -0,0 +1,24 @@
+use ry::{convert_docs, parse_path, parse_value, write};
+use yaml_rust::YamlLoader;
+
+#[test]
+fn test_write_multi_doc() {
+    let mut docs = YamlLoader::load_from_str("a:\n  b: 1\n---\na:\n  b: 2").unwrap();
+    let path = parse_path("a.b").unwrap();
+    write(&mut docs[1], &path, &parse_value("crab")).unwrap();
+    assert_eq!(
+        convert_docs(&docs).unwrap(),
+        "a:\n  b: 1\n---\na:\n  b: crab\n"
+    );
+}
+
+#[test]
+fn test_write_array_wildcard() {
+    let mut docs = YamlLoader::load_from_str("a:\n  - b: 1\n  - c: 2").unwrap();
+    let path = parse_path("a[*].b").unwrap();
+    write(&mut docs[0], &path, &parse_value("[1, 2]")).unwrap();
+    assert_eq!(
+        convert_docs(&docs).unwrap(),
+        "a:\n  - b:\n      - 1\n      - 2\n  - c: 2\n    b:\n      - 1\n      - 2\n"
+    );
+}